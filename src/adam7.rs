@@ -0,0 +1,231 @@
+//! Adam7 interlacing (PNG spec section 8.2).
+//!
+//! An interlaced image is stored as seven reduced images ("passes"), each
+//! filtered on its own and concatenated in the IDAT stream.
+
+use crate::{
+  filter::{self, FilterStrategy},
+  image_header::ImageHeader,
+  Error, Result,
+};
+
+/// `(x start, y start, x step, y step)` for each pass.
+const PASSES: [(usize, usize, usize, usize); 7] = [
+  (0, 0, 8, 8),
+  (4, 0, 8, 8),
+  (0, 4, 4, 8),
+  (2, 0, 4, 4),
+  (0, 2, 2, 4),
+  (1, 0, 2, 2),
+  (0, 1, 1, 2),
+];
+
+/// Width and height of each pass; a pass may be empty for small images.
+pub fn pass_sizes(width: u32, height: u32) -> [(u32, u32); 7] {
+  PASSES.map(|(x0, y0, dx, dy)| {
+    let size = |length: u32, start: usize, step: usize| {
+      (length as usize).saturating_sub(start).div_ceil(step) as u32
+    };
+    (size(width, x0, dx), size(height, y0, dy))
+  })
+}
+
+/// Size of the filtered data of all passes together.
+pub fn filtered_size(header: &ImageHeader) -> Option<usize> {
+  pass_sizes(header.width, header.height)
+    .iter()
+    .filter(|(w, h)| *w > 0 && *h > 0)
+    .try_fold(0usize, |total, &(w, h)| {
+      (header.row_bytes(w) + 1)
+        .checked_mul(h as usize)?
+        .checked_add(total)
+    })
+}
+
+/// Unfilters the seven passes in `filtered` and merges them into full
+/// resolution rows. The size of `filtered` is checked against the header
+/// before anything is allocated.
+pub fn deinterlace(filtered: &[u8], header: &ImageHeader) -> Result<Vec<u8>> {
+  if Some(filtered.len()) != filtered_size(header) {
+    return Err(wrong_size(filtered, header));
+  }
+  let bits = header.bits_per_pixel();
+  let bytes_per_pixel = bits / 8;
+  let row_bytes = header.row_bytes(header.width);
+  let output_size = row_bytes
+    .checked_mul(header.height as usize)
+    .ok_or_else(|| wrong_size(filtered, header))?;
+  let mut output = vec![0u8; output_size];
+
+  let mut offset = 0;
+  for (&(x0, y0, dx, dy), (w, h)) in PASSES.iter().zip(pass_sizes(header.width, header.height)) {
+    if w == 0 || h == 0 {
+      continue;
+    }
+    let pass_row_bytes = header.row_bytes(w);
+    let size = (pass_row_bytes + 1) * h as usize;
+    let pass = filtered
+      .get(offset..offset + size)
+      .ok_or_else(|| wrong_size(filtered, header))?;
+    let pass = filter::unfilter(pass, pass_row_bytes, h as usize, bytes_per_pixel)?;
+    offset += size;
+
+    for (y, pass_row) in pass.chunks_exact(pass_row_bytes).enumerate() {
+      let row = &mut output[(y0 + y * dy) * row_bytes..][..row_bytes];
+      for x in 0..w as usize {
+        copy_pixel(pass_row, x, row, x0 + x * dx, bits);
+      }
+    }
+  }
+
+  if offset != filtered.len() {
+    return Err(wrong_size(filtered, header));
+  }
+  Ok(output)
+}
+
+fn wrong_size(filtered: &[u8], header: &ImageHeader) -> Error {
+  Error::InvalidImageData(format!(
+    "expected {} bytes of filtered data, got {}",
+    filtered_size(header).unwrap_or(usize::MAX),
+    filtered.len()
+  ))
+}
+
+/// Splits full resolution rows into the seven passes and filters each.
+pub fn interlace(raw: &[u8], header: &ImageHeader, strategy: FilterStrategy) -> Vec<u8> {
+  let bits = header.bits_per_pixel();
+  let row_bytes = header.row_bytes(header.width);
+  let mut output = Vec::new();
+
+  for (&(x0, y0, dx, dy), (w, h)) in PASSES.iter().zip(pass_sizes(header.width, header.height)) {
+    if w == 0 || h == 0 {
+      continue;
+    }
+    let pass_row_bytes = header.row_bytes(w);
+    let mut pass = vec![0u8; pass_row_bytes * h as usize];
+    for (y, pass_row) in pass.chunks_exact_mut(pass_row_bytes).enumerate() {
+      let row = &raw[(y0 + y * dy) * row_bytes..][..row_bytes];
+      for x in 0..w as usize {
+        copy_pixel(row, x0 + x * dx, pass_row, x, bits);
+      }
+    }
+    output.extend(filter::filter(
+      &pass,
+      pass_row_bytes,
+      h as usize,
+      bits / 8,
+      strategy,
+    ));
+  }
+  output
+}
+
+/// Copies pixel `from` of `source` to pixel `to` of `target`, for pixels of
+/// `bits` bits packed most significant first.
+fn copy_pixel(source: &[u8], from: usize, target: &mut [u8], to: usize, bits: usize) {
+  if bits >= 8 {
+    let bytes = bits / 8;
+    target[to * bytes..][..bytes].copy_from_slice(&source[from * bytes..][..bytes]);
+    return;
+  }
+  let per_byte = 8 / bits;
+  let mask = ((1u16 << bits) - 1) as u8;
+  let source_shift = 8 - bits * (from % per_byte + 1);
+  let target_shift = 8 - bits * (to % per_byte + 1);
+  let value = (source[from / per_byte] >> source_shift) & mask;
+  let byte = &mut target[to / per_byte];
+  *byte = (*byte & !(mask << target_shift)) | (value << target_shift);
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::image_header::{ColorType, InterlaceMethod};
+
+  fn header(width: u32, height: u32, bit_depth: u8, color_type: ColorType) -> ImageHeader {
+    ImageHeader {
+      width,
+      height,
+      bit_depth,
+      color_type,
+      compression_method: 0,
+      filter_method: 0,
+      interlace_method: InterlaceMethod::Adam7,
+    }
+  }
+
+  #[test]
+  fn test_pass_sizes() {
+    assert_eq!(
+      pass_sizes(8, 8),
+      [(1, 1), (1, 1), (2, 1), (2, 2), (4, 2), (4, 4), (8, 4)]
+    );
+    assert_eq!(
+      pass_sizes(1, 1),
+      [(1, 1), (0, 1), (1, 0), (0, 1), (1, 0), (0, 1), (1, 0)]
+    );
+    assert_eq!(
+      pass_sizes(5, 3),
+      [(1, 1), (1, 1), (2, 0), (1, 1), (3, 1), (2, 2), (5, 1)]
+    );
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let cases = [
+      header(9, 9, 1, ColorType::Grayscale),
+      header(13, 5, 2, ColorType::Indexed),
+      header(3, 11, 4, ColorType::Grayscale),
+      header(10, 10, 8, ColorType::Rgb),
+      header(7, 6, 16, ColorType::Rgba),
+      header(1, 1, 8, ColorType::GrayscaleAlpha),
+    ];
+    for header in cases {
+      let row_bytes = header.row_bytes(header.width);
+      let raw: Vec<u8> = (0..row_bytes * header.height as usize)
+        .map(|i| (i * 37 + i / 3) as u8)
+        .collect();
+      // Padding bits at the end of each row are not part of any pixel.
+      let raw = mask_padding(raw, &header);
+
+      let filtered = interlace(&raw, &header, FilterStrategy::MinSum);
+      assert_eq!(Some(filtered.len()), filtered_size(&header));
+      assert_eq!(
+        deinterlace(&filtered, &header).unwrap(),
+        raw,
+        "{:?}",
+        header
+      );
+    }
+  }
+
+  fn mask_padding(mut raw: Vec<u8>, header: &ImageHeader) -> Vec<u8> {
+    let row_bytes = header.row_bytes(header.width);
+    let used_bits = header.width as usize * header.bits_per_pixel();
+    if !used_bits.is_multiple_of(8) {
+      let mask = !(0xFFu8 >> (used_bits % 8));
+      for row in raw.chunks_exact_mut(row_bytes) {
+        row[row_bytes - 1] &= mask;
+      }
+    }
+    raw
+  }
+
+  #[test]
+  fn test_wrong_size() {
+    let header = header(5, 3, 8, ColorType::Grayscale);
+    let filtered = interlace(&[0; 15], &header, FilterStrategy::MinSum);
+    assert!(deinterlace(&filtered[1..], &header).is_err());
+    assert!(deinterlace(&[filtered.clone(), vec![0]].concat(), &header).is_err());
+  }
+
+  #[test]
+  fn test_huge_header_with_little_data() {
+    let header = header(1 << 30, 1 << 30, 16, ColorType::Rgba);
+    assert!(matches!(
+      deinterlace(&[0; 64], &header),
+      Err(Error::InvalidImageData(_))
+    ));
+  }
+}
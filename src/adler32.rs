@@ -0,0 +1,66 @@
+/// Adler-32 as used by the zlib stream format (RFC 1950).
+const MOD_ADLER: u32 = 65521;
+
+/// Largest number of bytes that can be summed before `b` may overflow.
+const NMAX: usize = 5552;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Adler32 {
+  a: u32,
+  b: u32,
+}
+
+impl Default for Adler32 {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Adler32 {
+  pub fn new() -> Self {
+    Adler32 { a: 1, b: 0 }
+  }
+
+  pub fn update(&mut self, bytes: &[u8]) {
+    for block in bytes.chunks(NMAX) {
+      for &byte in block {
+        self.a += byte as u32;
+        self.b += self.a;
+      }
+      self.a %= MOD_ADLER;
+      self.b %= MOD_ADLER;
+    }
+  }
+
+  pub fn finish(&self) -> u32 {
+    (self.b << 16) | self.a
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn checksum(bytes: &[u8]) -> u32 {
+    let mut adler = Adler32::new();
+    adler.update(bytes);
+    adler.finish()
+  }
+
+  #[test]
+  pub fn test_checksum() {
+    assert_eq!(checksum(b""), 1);
+    assert_eq!(checksum(b"Wikipedia"), 0x11E6_0398);
+  }
+
+  #[test]
+  pub fn test_long_input() {
+    let bytes = vec![0xFF; 100_000];
+    let mut expected = (1u64, 0u64);
+    for &byte in &bytes {
+      expected.0 = (expected.0 + byte as u64) % 65521;
+      expected.1 = (expected.1 + expected.0) % 65521;
+    }
+    assert_eq!(checksum(&bytes), ((expected.1 << 16) | expected.0) as u32);
+  }
+}
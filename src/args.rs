@@ -1 +1,774 @@
+use std::path::PathBuf;
 
+use crate::{
+  lsb::{Channels, LsbOptions},
+  Error,
+};
+
+pub const USAGE: &str = "\
+Usage:
+  pngme encode [<encode options>] <file> <chunk_type> <message | --file <path>> [output]
+  pngme encode --lsb [<lsb options>] [<encode options>] <file> <message | --file <path>> [output]
+  pngme decode [<decryption>] [--output <path>] <file> <chunk_type>
+  pngme decode --name <name> [<decryption>] [--output <path>] <file>
+  pngme decode --lsb [<lsb options>] [<decryption>] [--output <path>] <file>
+  pngme verify [--signer <verifying_key_file>] <file> <chunk_type>
+  pngme remove <file> <chunk_type>
+  pngme remove --name <name> <file>
+  pngme list-messages <file>
+  pngme print <file>
+  pngme keygen [--signing] <secret_key_file> [public_key_file]
+  pngme key-info <key_file>
+
+Encode options: --compress, <encryption>, --sign-key <signing_key_file>,
+                --fragment-size <bytes>, --name <name>
+LSB options:    --bits <1-8> --channels <rgba>
+Encryption:     --password <password> | --recipient <public_key_file>
+Decryption:     --password <password> | --key <secret_key_file>
+
+--file stores a whole file with its name and MIME type instead of a text
+message; decode it with --output. --sign-key, --fragment-size and --name
+need a chunk type; --fragment-size splits the message across several
+chunks, and --name lets decode and remove find it among others of its type.
+A password of '-' is read from the first line of standard input.
+keygen --signing creates an Ed25519 key pair for --sign-key instead of an
+X25519 key pair for --recipient.";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PngMeArgs {
+  Encode(EncodeArgs),
+  Decode(DecodeArgs),
+  Verify(VerifyArgs),
+  Remove(RemoveArgs),
+  ListMessages(ListMessagesArgs),
+  Print(PrintArgs),
+  Keygen(KeygenArgs),
+  KeyInfo(KeyInfoArgs),
+}
+
+/// Where a message is stored: in a chunk of the given type, under a name
+/// from the manifest, or in the low bits of the pixel data.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Carrier {
+  Chunk(String),
+  /// Only read with `decode` and `remove`; `encode` takes a chunk type and
+  /// the name separately.
+  Named(String),
+  Pixels(LsbOptions),
+}
+
+/// What `encode` stores.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Message {
+  Text(String),
+  /// The contents of this file, along with its name.
+  File(PathBuf),
+}
+
+/// How `encode` protects the message, if at all.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Encryption {
+  Password(String),
+  /// Encrypt to the public key in this file.
+  Recipient(PathBuf),
+}
+
+/// How `decode` opens a protected message.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Decryption {
+  Password(String),
+  /// Decrypt with the secret key in this file.
+  SecretKey(PathBuf),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct EncodeArgs {
+  pub file_path: PathBuf,
+  pub carrier: Carrier,
+  pub message: Message,
+  pub output: Option<PathBuf>,
+  pub encryption: Option<Encryption>,
+  /// Compress the message before encrypting or storing it.
+  pub compress: bool,
+  /// Sign the stored chunk with the Ed25519 key in this file.
+  pub sign_key: Option<PathBuf>,
+  /// Split the message across chunks holding at most this many bytes of it.
+  pub fragment_size: Option<usize>,
+  /// List the message in the manifest under this name.
+  pub name: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DecodeArgs {
+  pub file_path: PathBuf,
+  pub carrier: Carrier,
+  pub decryption: Option<Decryption>,
+  /// Write the message to this file instead of standard output.
+  pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct VerifyArgs {
+  pub file_path: PathBuf,
+  pub chunk_type: String,
+  /// Require the signature to be made by the verifying key in this file.
+  pub signer: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RemoveArgs {
+  pub file_path: PathBuf,
+  /// A chunk type or a name, never the pixel data.
+  pub carrier: Carrier,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ListMessagesArgs {
+  pub file_path: PathBuf,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct PrintArgs {
+  pub file_path: PathBuf,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct KeygenArgs {
+  pub secret_key_path: PathBuf,
+  /// Defaults to the secret key path with `.pub` appended.
+  pub public_key_path: Option<PathBuf>,
+  /// Generate an Ed25519 signing key pair instead of an X25519 one.
+  pub signing: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct KeyInfoArgs {
+  pub key_path: PathBuf,
+}
+
+impl PngMeArgs {
+  /// Parses the command line, excluding the program name.
+  pub fn parse<I>(args: I) -> crate::Result<Self>
+  where
+    I: IntoIterator<Item = String>,
+  {
+    let mut args = args.into_iter();
+    let command = args
+      .next()
+      .ok_or_else(|| Error::Usage("missing subcommand".into()))?;
+    let mut options = Options::split(&command, args)?;
+    let positional = std::mem::take(&mut options.positional);
+
+    let parsed = match command.as_str() {
+      "encode" => {
+        let lsb = lsb_options(&mut options)?;
+        let encryption = encryption(&mut options)?;
+        let compress = options.flag("--compress");
+        let file = options.value("--file").map(PathBuf::from);
+        let sign_key = options.value("--sign-key").map(PathBuf::from);
+        let name = options.value("--name");
+        let fragment_size = options
+          .value("--fragment-size")
+          .map(|size| {
+            size.parse().ok().filter(|&size| size > 0).ok_or_else(|| {
+              Error::Usage(format!(
+                "encode: --fragment-size must be a positive number of bytes, got '{}'",
+                size
+              ))
+            })
+          })
+          .transpose()?;
+        if lsb.is_some() {
+          for (name, given) in [
+            ("--sign-key", sign_key.is_some()),
+            ("--fragment-size", fragment_size.is_some()),
+            ("--name", name.is_some()),
+          ] {
+            if given {
+              return Err(Error::Usage(format!(
+                "encode: {} needs a chunk type and cannot be combined with --lsb",
+                name
+              )));
+            }
+          }
+        }
+        options.finish()?;
+        let chunk_args = usize::from(lsb.is_none());
+        let message_args = usize::from(file.is_none());
+        let min = 1 + chunk_args + message_args;
+        expect_count(&command, &positional, min, min + 1)?;
+        let mut positional = positional.into_iter();
+        PngMeArgs::Encode(EncodeArgs {
+          file_path: positional.next().unwrap().into(),
+          carrier: carrier(lsb, &mut positional),
+          message: match file {
+            Some(path) => Message::File(path),
+            None => Message::Text(positional.next().unwrap()),
+          },
+          output: positional.next().map(PathBuf::from),
+          encryption,
+          compress,
+          sign_key,
+          fragment_size,
+          name,
+        })
+      }
+      "decode" => {
+        let lsb = lsb_options(&mut options)?;
+        let name = options.value("--name");
+        let decryption = decryption(&mut options)?;
+        let output = options.value("--output").map(PathBuf::from);
+        options.finish()?;
+        if lsb.is_some() && name.is_some() {
+          return Err(Error::Usage(
+            "decode: --name and --lsb cannot be combined".into(),
+          ));
+        }
+        let chunk_args = usize::from(lsb.is_none() && name.is_none());
+        expect_count(&command, &positional, 1 + chunk_args, 1 + chunk_args)?;
+        let mut positional = positional.into_iter();
+        let file_path = positional.next().unwrap().into();
+        PngMeArgs::Decode(DecodeArgs {
+          file_path,
+          carrier: match name {
+            Some(name) => Carrier::Named(name),
+            None => carrier(lsb, &mut positional),
+          },
+          decryption,
+          output,
+        })
+      }
+      "verify" => {
+        let signer = options.value("--signer").map(PathBuf::from);
+        options.finish()?;
+        expect_count(&command, &positional, 2, 2)?;
+        let mut positional = positional.into_iter();
+        PngMeArgs::Verify(VerifyArgs {
+          file_path: positional.next().unwrap().into(),
+          chunk_type: positional.next().unwrap(),
+          signer,
+        })
+      }
+      "remove" => {
+        let name = options.value("--name");
+        options.finish()?;
+        let chunk_args = usize::from(name.is_none());
+        expect_count(&command, &positional, 1 + chunk_args, 1 + chunk_args)?;
+        let mut positional = positional.into_iter();
+        PngMeArgs::Remove(RemoveArgs {
+          file_path: positional.next().unwrap().into(),
+          carrier: match name {
+            Some(name) => Carrier::Named(name),
+            None => Carrier::Chunk(positional.next().unwrap()),
+          },
+        })
+      }
+      "list-messages" => {
+        options.finish()?;
+        expect_count(&command, &positional, 1, 1)?;
+        PngMeArgs::ListMessages(ListMessagesArgs {
+          file_path: positional.into_iter().next().unwrap().into(),
+        })
+      }
+      "print" => {
+        options.finish()?;
+        expect_count(&command, &positional, 1, 1)?;
+        PngMeArgs::Print(PrintArgs {
+          file_path: positional.into_iter().next().unwrap().into(),
+        })
+      }
+      "keygen" => {
+        let signing = options.flag("--signing");
+        options.finish()?;
+        expect_count(&command, &positional, 1, 2)?;
+        let mut positional = positional.into_iter();
+        PngMeArgs::Keygen(KeygenArgs {
+          secret_key_path: positional.next().unwrap().into(),
+          public_key_path: positional.next().map(PathBuf::from),
+          signing,
+        })
+      }
+      "key-info" => {
+        options.finish()?;
+        expect_count(&command, &positional, 1, 1)?;
+        PngMeArgs::KeyInfo(KeyInfoArgs {
+          key_path: positional.into_iter().next().unwrap().into(),
+        })
+      }
+      other => return Err(Error::Usage(format!("unknown subcommand '{}'", other))),
+    };
+
+    Ok(parsed)
+  }
+}
+
+/// Options given before or between the positional arguments. Everything
+/// after a bare `--` is positional.
+struct Options {
+  command: String,
+  positional: Vec<String>,
+  options: Vec<(String, Option<String>)>,
+}
+
+/// Options that take a value; every other `--name` is a plain flag.
+const VALUE_OPTIONS: &[&str] = &[
+  "--bits",
+  "--channels",
+  "--password",
+  "--recipient",
+  "--key",
+  "--sign-key",
+  "--signer",
+  "--file",
+  "--output",
+  "--fragment-size",
+  "--name",
+];
+
+impl Options {
+  fn split(command: &str, mut args: impl Iterator<Item = String>) -> crate::Result<Self> {
+    let mut split = Options {
+      command: command.to_string(),
+      positional: Vec::new(),
+      options: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+      if arg == "--" {
+        split.positional.extend(args.by_ref());
+      } else if arg.starts_with("--") {
+        let value = if VALUE_OPTIONS.contains(&arg.as_str()) {
+          Some(
+            args
+              .next()
+              .ok_or_else(|| Error::Usage(format!("{}: {} needs a value", command, arg)))?,
+          )
+        } else {
+          None
+        };
+        if split.options.iter().any(|(name, _)| *name == arg) {
+          return Err(Error::Usage(format!("{}: {} given twice", command, arg)));
+        }
+        split.options.push((arg, value));
+      } else {
+        split.positional.push(arg);
+      }
+    }
+    Ok(split)
+  }
+
+  /// Removes `name` and reports whether it was given.
+  fn flag(&mut self, name: &str) -> bool {
+    self.value(name).is_some()
+  }
+
+  /// Removes `name` and returns its value, or an empty string for a flag.
+  fn value(&mut self, name: &str) -> Option<String> {
+    let index = self.options.iter().position(|(option, _)| option == name)?;
+    Some(self.options.remove(index).1.unwrap_or_default())
+  }
+
+  /// Rejects any option the command did not ask for.
+  fn finish(self) -> crate::Result<()> {
+    match self.options.first() {
+      Some((name, _)) => Err(Error::Usage(format!(
+        "{}: unknown option '{}'",
+        self.command, name
+      ))),
+      None => Ok(()),
+    }
+  }
+}
+
+/// Reads `--lsb`, `--bits` and `--channels`. The last two only make sense
+/// together with the first.
+fn lsb_options(options: &mut Options) -> crate::Result<Option<LsbOptions>> {
+  let lsb = options.flag("--lsb");
+  let bits = options.value("--bits");
+  let channels = options.value("--channels");
+  if !lsb {
+    for (name, value) in [("--bits", &bits), ("--channels", &channels)] {
+      if value.is_some() {
+        return Err(Error::Usage(format!(
+          "{}: {} requires --lsb",
+          options.command, name
+        )));
+      }
+    }
+    return Ok(None);
+  }
+
+  let mut lsb = LsbOptions::default();
+  if let Some(bits) = bits {
+    lsb.bits_per_channel = bits
+      .parse()
+      .ok()
+      .filter(|bits| (1..=8).contains(bits))
+      .ok_or_else(|| {
+        Error::Usage(format!(
+          "{}: --bits must be between 1 and 8, got '{}'",
+          options.command, bits
+        ))
+      })?;
+  }
+  if let Some(channels) = channels {
+    lsb.channels = channels.parse::<Channels>()?;
+  }
+  Ok(Some(lsb))
+}
+
+fn encryption(options: &mut Options) -> crate::Result<Option<Encryption>> {
+  match (options.value("--password"), options.value("--recipient")) {
+    (Some(_), Some(_)) => Err(Error::Usage(format!(
+      "{}: --password and --recipient cannot be combined",
+      options.command
+    ))),
+    (Some(password), None) => Ok(Some(Encryption::Password(password))),
+    (None, Some(path)) => Ok(Some(Encryption::Recipient(path.into()))),
+    (None, None) => Ok(None),
+  }
+}
+
+fn decryption(options: &mut Options) -> crate::Result<Option<Decryption>> {
+  match (options.value("--password"), options.value("--key")) {
+    (Some(_), Some(_)) => Err(Error::Usage(format!(
+      "{}: --password and --key cannot be combined",
+      options.command
+    ))),
+    (Some(password), None) => Ok(Some(Decryption::Password(password))),
+    (None, Some(path)) => Ok(Some(Decryption::SecretKey(path.into()))),
+    (None, None) => Ok(None),
+  }
+}
+
+fn carrier(lsb: Option<LsbOptions>, positional: &mut impl Iterator<Item = String>) -> Carrier {
+  match lsb {
+    Some(options) => Carrier::Pixels(options),
+    None => Carrier::Chunk(positional.next().unwrap()),
+  }
+}
+
+fn expect_count(command: &str, positional: &[String], min: usize, max: usize) -> crate::Result<()> {
+  if positional.len() < min {
+    return Err(Error::Usage(format!("{}: missing arguments", command)));
+  }
+  if positional.len() > max {
+    return Err(Error::Usage(format!(
+      "{}: unexpected argument '{}'",
+      command, positional[max]
+    )));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(args: &[&str]) -> crate::Result<PngMeArgs> {
+    PngMeArgs::parse(args.iter().map(|arg| arg.to_string()))
+  }
+
+  #[test]
+  fn test_parse_encode() {
+    let args = parse(&["encode", "in.png", "RuSt", "hello", "out.png"]).unwrap();
+    assert_eq!(
+      args,
+      PngMeArgs::Encode(EncodeArgs {
+        file_path: "in.png".into(),
+        carrier: Carrier::Chunk("RuSt".into()),
+        message: Message::Text("hello".into()),
+        output: Some("out.png".into()),
+        encryption: None,
+        compress: false,
+        sign_key: None,
+        fragment_size: None,
+        name: None,
+      })
+    );
+  }
+
+  #[test]
+  fn test_parse_encode_without_output() {
+    let args = parse(&["encode", "in.png", "RuSt", "hello"]).unwrap();
+    assert!(matches!(
+      args,
+      PngMeArgs::Encode(EncodeArgs { output: None, .. })
+    ));
+  }
+
+  #[test]
+  fn test_parse_decode_remove_print() {
+    assert!(matches!(
+      parse(&["decode", "in.png", "RuSt"]).unwrap(),
+      PngMeArgs::Decode(_)
+    ));
+    assert!(matches!(
+      parse(&["remove", "in.png", "RuSt"]).unwrap(),
+      PngMeArgs::Remove(_)
+    ));
+    assert!(matches!(
+      parse(&["print", "in.png"]).unwrap(),
+      PngMeArgs::Print(_)
+    ));
+  }
+
+  #[test]
+  fn test_parse_lsb() {
+    let args = parse(&[
+      "encode",
+      "--lsb",
+      "--bits",
+      "2",
+      "in.png",
+      "hi",
+      "--channels",
+      "rgba",
+    ])
+    .unwrap();
+    assert_eq!(
+      args,
+      PngMeArgs::Encode(EncodeArgs {
+        file_path: "in.png".into(),
+        carrier: Carrier::Pixels(LsbOptions {
+          bits_per_channel: 2,
+          channels: "rgba".parse().unwrap(),
+        }),
+        message: Message::Text("hi".into()),
+        output: None,
+        encryption: None,
+        compress: false,
+        sign_key: None,
+        fragment_size: None,
+        name: None,
+      })
+    );
+    assert_eq!(
+      parse(&["decode", "--lsb", "in.png"]).unwrap(),
+      PngMeArgs::Decode(DecodeArgs {
+        file_path: "in.png".into(),
+        carrier: Carrier::Pixels(LsbOptions::default()),
+        decryption: None,
+        output: None,
+      })
+    );
+  }
+
+  #[test]
+  fn test_parse_password() {
+    assert!(matches!(
+      parse(&["encode", "in.png", "RuSt", "hi", "--password", "pw"]).unwrap(),
+      PngMeArgs::Encode(EncodeArgs {
+        encryption: Some(Encryption::Password(password)),
+        ..
+      }) if password == "pw"
+    ));
+    assert!(matches!(
+      parse(&["decode", "--password", "-", "--lsb", "in.png"]).unwrap(),
+      PngMeArgs::Decode(DecodeArgs {
+        decryption: Some(Decryption::Password(password)),
+        ..
+      }) if password == "-"
+    ));
+    assert!(parse(&["remove", "--password", "pw", "in.png", "RuSt"]).is_err());
+  }
+
+  #[test]
+  fn test_parse_keys() {
+    assert!(matches!(
+      parse(&["encode", "--recipient", "bob.pub", "in.png", "RuSt", "hi"]).unwrap(),
+      PngMeArgs::Encode(EncodeArgs {
+        encryption: Some(Encryption::Recipient(path)),
+        ..
+      }) if path.as_os_str() == "bob.pub"
+    ));
+    assert!(matches!(
+      parse(&["decode", "--key", "bob.key", "in.png", "RuSt"]).unwrap(),
+      PngMeArgs::Decode(DecodeArgs {
+        decryption: Some(Decryption::SecretKey(path)),
+        ..
+      }) if path.as_os_str() == "bob.key"
+    ));
+    assert!(parse(&[
+      "encode",
+      "--recipient",
+      "a",
+      "--password",
+      "b",
+      "in.png",
+      "RuSt",
+      "hi"
+    ])
+    .is_err());
+    assert!(parse(&["decode", "--recipient", "bob.pub", "in.png", "RuSt"]).is_err());
+    assert!(parse(&["encode", "--key", "bob.key", "in.png", "RuSt", "hi"]).is_err());
+
+    assert_eq!(
+      parse(&["keygen", "bob.key"]).unwrap(),
+      PngMeArgs::Keygen(KeygenArgs {
+        secret_key_path: "bob.key".into(),
+        public_key_path: None,
+        signing: false,
+      })
+    );
+    assert!(matches!(
+      parse(&["keygen", "--signing", "alice.key", "alice.pub"]).unwrap(),
+      PngMeArgs::Keygen(KeygenArgs {
+        signing: true,
+        public_key_path: Some(_),
+        ..
+      })
+    ));
+    assert!(matches!(
+      parse(&["key-info", "bob.pub"]).unwrap(),
+      PngMeArgs::KeyInfo(_)
+    ));
+  }
+
+  #[test]
+  fn test_parse_compress() {
+    assert!(matches!(
+      parse(&["encode", "--compress", "in.png", "RuSt", "hi"]).unwrap(),
+      PngMeArgs::Encode(EncodeArgs { compress: true, .. })
+    ));
+    assert!(matches!(
+      parse(&["encode", "--lsb", "in.png", "hi", "--compress"]).unwrap(),
+      PngMeArgs::Encode(EncodeArgs { compress: true, .. })
+    ));
+    assert!(parse(&["decode", "--compress", "in.png", "RuSt"]).is_err());
+  }
+
+  #[test]
+  fn test_parse_files() {
+    assert_eq!(
+      parse(&["encode", "--file", "key.bin", "in.png", "RuSt", "out.png"]).unwrap(),
+      PngMeArgs::Encode(EncodeArgs {
+        file_path: "in.png".into(),
+        carrier: Carrier::Chunk("RuSt".into()),
+        message: Message::File("key.bin".into()),
+        output: Some("out.png".into()),
+        encryption: None,
+        compress: false,
+        sign_key: None,
+        fragment_size: None,
+        name: None,
+      })
+    );
+    assert!(matches!(
+      parse(&["encode", "--lsb", "--file", "key.bin", "in.png"]).unwrap(),
+      PngMeArgs::Encode(EncodeArgs {
+        message: Message::File(_),
+        output: None,
+        ..
+      })
+    ));
+    assert!(parse(&["encode", "--file", "key.bin", "in.png", "RuSt", "hi", "out.png"]).is_err());
+    assert!(matches!(
+      parse(&["decode", "--output", "out.bin", "in.png", "RuSt"]).unwrap(),
+      PngMeArgs::Decode(DecodeArgs {
+        output: Some(path),
+        ..
+      }) if path.as_os_str() == "out.bin"
+    ));
+    assert!(parse(&["decode", "--file", "x", "in.png", "RuSt"]).is_err());
+  }
+
+  #[test]
+  fn test_parse_fragment_size() {
+    assert!(matches!(
+      parse(&["encode", "--fragment-size", "4096", "in.png", "RuSt", "hi"]).unwrap(),
+      PngMeArgs::Encode(EncodeArgs {
+        fragment_size: Some(4096),
+        ..
+      })
+    ));
+    for size in ["0", "-1", "big"] {
+      assert!(parse(&["encode", "--fragment-size", size, "in.png", "RuSt", "hi"]).is_err());
+    }
+    assert!(parse(&["encode", "--lsb", "--fragment-size", "10", "in.png", "hi"]).is_err());
+  }
+
+  #[test]
+  fn test_parse_names() {
+    assert!(matches!(
+      parse(&["encode", "--name", "notes", "in.png", "RuSt", "hi"]).unwrap(),
+      PngMeArgs::Encode(EncodeArgs { name: Some(name), .. }) if name == "notes"
+    ));
+    assert_eq!(
+      parse(&["decode", "--name", "notes", "--password", "pw", "in.png"]).unwrap(),
+      PngMeArgs::Decode(DecodeArgs {
+        file_path: "in.png".into(),
+        carrier: Carrier::Named("notes".into()),
+        decryption: Some(Decryption::Password("pw".into())),
+        output: None,
+      })
+    );
+    assert_eq!(
+      parse(&["remove", "--name", "notes", "in.png"]).unwrap(),
+      PngMeArgs::Remove(RemoveArgs {
+        file_path: "in.png".into(),
+        carrier: Carrier::Named("notes".into()),
+      })
+    );
+    assert!(matches!(
+      parse(&["list-messages", "in.png"]).unwrap(),
+      PngMeArgs::ListMessages(_)
+    ));
+    assert!(parse(&["encode", "--lsb", "--name", "notes", "in.png", "hi"]).is_err());
+    assert!(parse(&["decode", "--lsb", "--name", "notes", "in.png"]).is_err());
+    assert!(parse(&["decode", "--name", "notes", "in.png", "RuSt"]).is_err());
+    assert!(parse(&["remove", "--name", "notes", "in.png", "RuSt"]).is_err());
+    assert!(parse(&["list-messages", "in.png", "RuSt"]).is_err());
+  }
+
+  #[test]
+  fn test_parse_signatures() {
+    assert!(matches!(
+      parse(&["encode", "--sign-key", "alice.key", "in.png", "RuSt", "hi"]).unwrap(),
+      PngMeArgs::Encode(EncodeArgs {
+        sign_key: Some(path),
+        ..
+      }) if path.as_os_str() == "alice.key"
+    ));
+    assert!(parse(&["encode", "--lsb", "--sign-key", "alice.key", "in.png", "hi"]).is_err());
+    assert_eq!(
+      parse(&["verify", "in.png", "RuSt", "--signer", "alice.pub"]).unwrap(),
+      PngMeArgs::Verify(VerifyArgs {
+        file_path: "in.png".into(),
+        chunk_type: "RuSt".into(),
+        signer: Some("alice.pub".into()),
+      })
+    );
+    assert!(parse(&["verify", "in.png"]).is_err());
+    assert!(parse(&["decode", "--signer", "alice.pub", "in.png", "RuSt"]).is_err());
+  }
+
+  #[test]
+  fn test_parse_option_errors() {
+    assert!(parse(&["encode", "--bits", "2", "in.png", "RuSt", "hi"]).is_err());
+    assert!(parse(&["encode", "--lsb", "--bits", "9", "in.png", "hi"]).is_err());
+    assert!(parse(&["encode", "--lsb", "--bits"]).is_err());
+    assert!(parse(&["decode", "--lsb", "--lsb", "in.png"]).is_err());
+    assert!(parse(&["print", "--lsb", "in.png"]).is_err());
+    assert!(parse(&["decode", "--lsb", "in.png", "RuSt"]).is_err());
+  }
+
+  #[test]
+  fn test_parse_double_dash() {
+    let args = parse(&["encode", "in.png", "RuSt", "--", "--not-an-option"]).unwrap();
+    assert!(matches!(
+      args,
+      PngMeArgs::Encode(EncodeArgs {
+        message: Message::Text(message),
+        ..
+      }) if message == "--not-an-option"
+    ));
+  }
+
+  #[test]
+  fn test_parse_errors() {
+    assert!(parse(&[]).is_err());
+    assert!(parse(&["frobnicate"]).is_err());
+    assert!(parse(&["decode", "in.png"]).is_err());
+    assert!(parse(&["print", "in.png", "extra"]).is_err());
+  }
+}
@@ -0,0 +1,225 @@
+//! Whole files carried as messages, with the name and MIME type they were
+//! embedded with.
+//!
+//! An attachment is the content of an [`Envelope`] whose content type is
+//! [`ContentType::File`], so it is compressed and encrypted along with the
+//! file data and its name does not leak. It is laid out as:
+//!
+//! | bytes | field                                 |
+//! |-------|---------------------------------------|
+//! | 2     | name length, big-endian               |
+//! | n     | file name, UTF-8, no directories      |
+//! | 1     | MIME type length                      |
+//! | m     | MIME type, ASCII                      |
+//! | rest  | file contents                         |
+//!
+//! [`Envelope`]: crate::envelope::Envelope
+//! [`ContentType::File`]: crate::envelope::ContentType::File
+
+use std::path::Path;
+
+use crate::{bytes::take, Error, Result};
+
+/// Used when neither the extension nor the contents say otherwise.
+pub const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// MIME types by lowercase file extension.
+const EXTENSIONS: &[(&str, &str)] = &[
+  ("bin", DEFAULT_MIME_TYPE),
+  ("csv", "text/csv"),
+  ("gif", "image/gif"),
+  ("gz", "application/gzip"),
+  ("html", "text/html"),
+  ("jpeg", "image/jpeg"),
+  ("jpg", "image/jpeg"),
+  ("json", "application/json"),
+  ("md", "text/markdown"),
+  ("pdf", "application/pdf"),
+  ("pem", "application/x-pem-file"),
+  ("png", "image/png"),
+  ("tar", "application/x-tar"),
+  ("txt", "text/plain"),
+  ("xml", "application/xml"),
+  ("zip", "application/zip"),
+];
+
+/// MIME types by leading bytes, for files without a known extension.
+const SIGNATURES: &[(&[u8], &str)] = &[
+  (b"\x89PNG\r\n\x1a\n", "image/png"),
+  (b"\xff\xd8\xff", "image/jpeg"),
+  (b"GIF8", "image/gif"),
+  (b"%PDF-", "application/pdf"),
+  (b"PK\x03\x04", "application/zip"),
+  (b"\x1f\x8b", "application/gzip"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+  name: String,
+  mime_type: String,
+  data: Vec<u8>,
+}
+
+impl TryFrom<&[u8]> for Attachment {
+  type Error = Error;
+
+  fn try_from(bytes: &[u8]) -> Result<Self> {
+    let truncated = || Error::InvalidAttachment("truncated header".to_string());
+    let mut rest = bytes;
+    let name_length = take(&mut rest, 2).ok_or_else(truncated)?;
+    let name_length = u16::from_be_bytes([name_length[0], name_length[1]]);
+    let name = take(&mut rest, name_length as usize).ok_or_else(truncated)?;
+    let mime_length = take(&mut rest, 1).ok_or_else(truncated)?[0];
+    let mime_type = take(&mut rest, mime_length as usize).ok_or_else(truncated)?;
+
+    let name = String::from_utf8(name.to_vec())
+      .map_err(|_| Error::InvalidAttachment("file name is not valid utf-8".to_string()))?;
+    let mime_type = String::from_utf8(mime_type.to_vec())
+      .map_err(|_| Error::InvalidAttachment("mime type is not valid utf-8".to_string()))?;
+    Attachment::new(name, mime_type, rest.to_vec())
+  }
+}
+
+impl Attachment {
+  /// Checks that `name` is a plain file name and `mime_type` is short
+  /// printable ASCII.
+  pub fn new(name: String, mime_type: String, data: Vec<u8>) -> Result<Self> {
+    if name.is_empty()
+      || name.len() > u16::MAX as usize
+      || name == "."
+      || name == ".."
+      || name.contains(['/', '\\', '\0'])
+    {
+      return Err(Error::InvalidAttachment(format!(
+        "'{}' is not a plain file name",
+        name
+      )));
+    }
+    if mime_type.is_empty()
+      || mime_type.len() > u8::MAX as usize
+      || !mime_type.bytes().all(|b| b.is_ascii_graphic())
+    {
+      return Err(Error::InvalidAttachment(format!(
+        "'{}' is not a valid mime type",
+        mime_type
+      )));
+    }
+    Ok(Attachment {
+      name,
+      mime_type,
+      data,
+    })
+  }
+
+  /// Reads the file at `path`, keeping only its final name component and
+  /// guessing its MIME type.
+  pub fn from_file(path: &Path) -> Result<Self> {
+    let data = std::fs::read(path)?;
+    let name = path
+      .file_name()
+      .and_then(|name| name.to_str())
+      .ok_or_else(|| {
+        Error::InvalidAttachment(format!("'{}' has no usable file name", path.display()))
+      })?
+      .to_string();
+    let mime_type = guess_mime_type(&name, &data).to_string();
+    Attachment::new(name, mime_type, data)
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn mime_type(&self) -> &str {
+    &self.mime_type
+  }
+
+  pub fn data(&self) -> &[u8] {
+    &self.data
+  }
+
+  pub fn into_data(self) -> Vec<u8> {
+    self.data
+  }
+
+  pub fn as_bytes(&self) -> Vec<u8> {
+    let mut bytes =
+      Vec::with_capacity(3 + self.name.len() + self.mime_type.len() + self.data.len());
+    bytes.extend_from_slice(&(self.name.len() as u16).to_be_bytes());
+    bytes.extend_from_slice(self.name.as_bytes());
+    bytes.push(self.mime_type.len() as u8);
+    bytes.extend_from_slice(self.mime_type.as_bytes());
+    bytes.extend_from_slice(&self.data);
+    bytes
+  }
+}
+
+/// Guesses a MIME type from the file extension, then from the first bytes.
+pub fn guess_mime_type(name: &str, data: &[u8]) -> &'static str {
+  let extension = name
+    .rsplit_once('.')
+    .map(|(_, extension)| extension.to_ascii_lowercase());
+  if let Some(extension) = extension {
+    if let Some((_, mime_type)) = EXTENSIONS.iter().find(|(known, _)| *known == extension) {
+      return mime_type;
+    }
+  }
+  SIGNATURES
+    .iter()
+    .find(|(signature, _)| data.starts_with(signature))
+    .map_or(DEFAULT_MIME_TYPE, |(_, mime_type)| mime_type)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_round_trip() {
+    let data: Vec<u8> = (0..=255).collect();
+    let attachment =
+      Attachment::new("key.bin".into(), DEFAULT_MIME_TYPE.into(), data.clone()).unwrap();
+    let bytes = attachment.as_bytes();
+    assert_eq!(&bytes[..9], b"\x00\x07key.bin");
+    let parsed = Attachment::try_from(bytes.as_slice()).unwrap();
+    assert_eq!(parsed, attachment);
+    assert_eq!(parsed.into_data(), data);
+  }
+
+  #[test]
+  fn test_rejects_unsafe_names() {
+    for name in ["", ".", "..", "../etc/passwd", "a/b", "a\\b", "a\0b"] {
+      assert!(
+        Attachment::new(name.into(), "text/plain".into(), vec![]).is_err(),
+        "{:?}",
+        name
+      );
+    }
+    assert!(Attachment::new("a.txt".into(), "text plain".into(), vec![]).is_err());
+  }
+
+  #[test]
+  fn test_rejects_truncated_bytes() {
+    let bytes = Attachment::new("a.txt".into(), "text/plain".into(), vec![1, 2])
+      .unwrap()
+      .as_bytes();
+    for end in [0, 1, 5, 7, 12] {
+      assert!(matches!(
+        Attachment::try_from(&bytes[..end]),
+        Err(Error::InvalidAttachment(_))
+      ));
+    }
+    assert!(Attachment::try_from(&bytes[..bytes.len() - 2]).is_ok());
+  }
+
+  #[test]
+  fn test_guess_mime_type() {
+    assert_eq!(guess_mime_type("notes.TXT", b""), "text/plain");
+    assert_eq!(guess_mime_type("archive.tar.gz", b""), "application/gzip");
+    assert_eq!(
+      guess_mime_type("image", b"\x89PNG\r\n\x1a\n...."),
+      "image/png"
+    );
+    assert_eq!(guess_mime_type("blob", b"\x00\x01"), DEFAULT_MIME_TYPE);
+  }
+}
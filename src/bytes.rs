@@ -0,0 +1,28 @@
+//! Helpers for parsing the fixed layouts of the crate's own chunk formats.
+
+/// Splits `n` bytes off the front of `bytes`, or returns `None` if fewer
+/// than `n` are left.
+pub(crate) fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+  if bytes.len() < n {
+    return None;
+  }
+  let (head, tail) = bytes.split_at(n);
+  *bytes = tail;
+  Some(head)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_take() {
+    let mut rest = &b"abcde"[..];
+    assert_eq!(take(&mut rest, 2), Some(&b"ab"[..]));
+    assert_eq!(take(&mut rest, 0), Some(&b""[..]));
+    assert_eq!(take(&mut rest, 4), None);
+    assert_eq!(rest, b"cde");
+    assert_eq!(take(&mut rest, 3), Some(&b"cde"[..]));
+    assert!(rest.is_empty());
+  }
+}
@@ -1 +1,308 @@
+use std::fmt::Display;
 
+use crate::{chunk_ref::ChunkRef, chunk_type::ChunkType, crc, Error};
+
+#[derive(Debug, Clone)]
+pub struct Chunk {
+  length: u32,
+  chunk_type: ChunkType,
+  data: Vec<u8>,
+  crc: u32,
+}
+
+impl TryFrom<&[u8]> for Chunk {
+  type Error = Error;
+  fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+    ChunkRef::try_from(value).map(Chunk::from)
+  }
+}
+
+impl Display for Chunk {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "Chunk {{ length: {}, type: {}, data: {} bytes, crc: {} }}",
+      self.length,
+      self.chunk_type,
+      self.data.len(),
+      self.crc
+    )
+  }
+}
+
+impl Chunk {
+  /// The most data one chunk can hold: 2^31 - 1 bytes (PNG spec section
+  /// 5.3).
+  pub const MAX_LENGTH: usize = i32::MAX as usize;
+
+  /// Builds a chunk from data known to fit in one chunk.
+  ///
+  /// # Panics
+  ///
+  /// If `data` is longer than [`Chunk::MAX_LENGTH`]; use [`Chunk::try_new`]
+  /// for data of unchecked size.
+  pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Self {
+    match Chunk::try_new(chunk_type, data) {
+      Ok(chunk) => chunk,
+      Err(e) => panic!("{}", e),
+    }
+  }
+
+  /// Builds a chunk, or fails if `data` is longer than
+  /// [`Chunk::MAX_LENGTH`].
+  pub fn try_new(chunk_type: ChunkType, data: Vec<u8>) -> crate::Result<Self> {
+    if data.len() > Chunk::MAX_LENGTH {
+      return Err(Error::ChunkTooLarge(data.len()));
+    }
+    let mut hasher = crc::Crc32::new();
+    hasher.update(&chunk_type.bytes());
+    hasher.update(&data);
+
+    Ok(Chunk {
+      length: data.len() as u32,
+      chunk_type,
+      data,
+      crc: hasher.finish(),
+    })
+  }
+
+  /// Builds a chunk from a parsed one, whose length field and CRC are
+  /// already known to match `data`.
+  pub(crate) fn from_parts(length: u32, chunk_type: ChunkType, data: Vec<u8>, crc: u32) -> Self {
+    debug_assert_eq!(length as usize, data.len());
+    Chunk {
+      length,
+      chunk_type,
+      data,
+      crc,
+    }
+  }
+
+  pub fn length(&self) -> u32 {
+    self.length
+  }
+
+  pub fn chunk_type(&self) -> &ChunkType {
+    &self.chunk_type
+  }
+
+  pub fn data(&self) -> &[u8] {
+    &self.data
+  }
+
+  pub fn crc(&self) -> u32 {
+    self.crc
+  }
+
+  pub fn data_as_string(&self) -> crate::Result<String> {
+    Ok(String::from_utf8(self.data.clone())?)
+  }
+
+  pub fn as_bytes(&self) -> Vec<u8> {
+    self
+      .length
+      .to_be_bytes()
+      .iter()
+      .chain(self.chunk_type.bytes().iter())
+      .chain(self.data.iter())
+      .chain(self.crc.to_be_bytes().iter())
+      .copied()
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::chunk_type::ChunkType;
+  use std::str::FromStr;
+
+  fn testing_chunk() -> Chunk {
+    let data_length: u32 = 42;
+    let chunk_type = "RuSt".as_bytes();
+    let message_bytes = "This is where your secret message will be!".as_bytes();
+    let crc: u32 = 2882656334;
+
+    let chunk_data: Vec<u8> = data_length
+      .to_be_bytes()
+      .iter()
+      .chain(chunk_type.iter())
+      .chain(message_bytes.iter())
+      .chain(crc.to_be_bytes().iter())
+      .copied()
+      .collect();
+
+    Chunk::try_from(chunk_data.as_ref()).unwrap()
+  }
+
+  #[test]
+  fn test_new_chunk() {
+    let chunk_type = ChunkType::from_str("RuSt").unwrap();
+    let data = "This is where your secret message will be!"
+      .as_bytes()
+      .to_vec();
+    let chunk = Chunk::new(chunk_type, data);
+    assert_eq!(chunk.length(), 42);
+    assert_eq!(chunk.crc(), 2882656334);
+  }
+
+  #[test]
+  fn test_try_new_rejects_oversized_data() {
+    let chunk_type = ChunkType::from_str("RuSt").unwrap();
+    assert_eq!(
+      Chunk::try_new(chunk_type, b"fits".to_vec())
+        .unwrap()
+        .length(),
+      4
+    );
+    // Zeroed and never touched, so this does not use 2 GiB of memory.
+    let data = vec![0u8; Chunk::MAX_LENGTH + 1];
+    assert!(matches!(
+      Chunk::try_new(chunk_type, data),
+      Err(Error::ChunkTooLarge(length)) if length == Chunk::MAX_LENGTH + 1
+    ));
+  }
+
+  #[test]
+  fn test_chunk_length() {
+    let chunk = testing_chunk();
+    assert_eq!(chunk.length(), 42);
+  }
+
+  #[test]
+  fn test_chunk_type() {
+    let chunk = testing_chunk();
+    assert_eq!(chunk.chunk_type().to_string(), String::from("RuSt"));
+  }
+
+  #[test]
+  fn test_chunk_string() {
+    let chunk = testing_chunk();
+    let chunk_string = chunk.data_as_string().unwrap();
+    let expected_chunk_string = String::from("This is where your secret message will be!");
+    assert_eq!(chunk_string, expected_chunk_string);
+  }
+
+  #[test]
+  fn test_chunk_crc() {
+    let chunk = testing_chunk();
+    assert_eq!(chunk.crc(), 2882656334);
+  }
+
+  #[test]
+  fn test_valid_chunk_from_bytes() {
+    let data_length: u32 = 42;
+    let chunk_type = "RuSt".as_bytes();
+    let message_bytes = "This is where your secret message will be!".as_bytes();
+    let crc: u32 = 2882656334;
+
+    let chunk_data: Vec<u8> = data_length
+      .to_be_bytes()
+      .iter()
+      .chain(chunk_type.iter())
+      .chain(message_bytes.iter())
+      .chain(crc.to_be_bytes().iter())
+      .copied()
+      .collect();
+
+    let chunk = Chunk::try_from(chunk_data.as_ref()).unwrap();
+
+    let chunk_string = chunk.data_as_string().unwrap();
+    let expected_chunk_string = String::from("This is where your secret message will be!");
+
+    assert_eq!(chunk.length(), 42);
+    assert_eq!(chunk.chunk_type().to_string(), String::from("RuSt"));
+    assert_eq!(chunk_string, expected_chunk_string);
+    assert_eq!(chunk.crc(), 2882656334);
+  }
+
+  #[test]
+  fn test_invalid_chunk_from_bytes() {
+    let data_length: u32 = 42;
+    let chunk_type = "RuSt".as_bytes();
+    let message_bytes = "This is where your secret message will be!".as_bytes();
+    let crc: u32 = 2882656333;
+
+    let chunk_data: Vec<u8> = data_length
+      .to_be_bytes()
+      .iter()
+      .chain(chunk_type.iter())
+      .chain(message_bytes.iter())
+      .chain(crc.to_be_bytes().iter())
+      .copied()
+      .collect();
+
+    let chunk = Chunk::try_from(chunk_data.as_ref());
+
+    assert!(matches!(
+      chunk,
+      Err(Error::CrcMismatch {
+        offset: 0,
+        expected: 2882656334,
+        found: 2882656333
+      })
+    ));
+  }
+
+  #[test]
+  fn test_truncated_chunk_from_bytes() {
+    let chunk = testing_chunk();
+    let bytes = chunk.as_bytes();
+
+    assert!(matches!(
+      Chunk::try_from(&bytes[..bytes.len() - 1]),
+      Err(Error::Truncated {
+        needed: 54,
+        available: 53,
+        ..
+      })
+    ));
+    assert!(matches!(
+      Chunk::try_from(&bytes[..8]),
+      Err(Error::Truncated { .. })
+    ));
+  }
+
+  #[test]
+  fn test_chunk_with_trailing_bytes() {
+    let mut bytes = testing_chunk().as_bytes();
+    bytes.push(0);
+
+    assert!(matches!(
+      Chunk::try_from(bytes.as_ref()),
+      Err(Error::LengthMismatch {
+        declared: 42,
+        actual: 43,
+        ..
+      })
+    ));
+  }
+
+  #[test]
+  fn test_chunk_as_bytes_round_trip() {
+    let chunk = testing_chunk();
+    let parsed = Chunk::try_from(chunk.as_bytes().as_ref()).unwrap();
+    assert_eq!(parsed.as_bytes(), chunk.as_bytes());
+  }
+
+  #[test]
+  pub fn test_chunk_trait_impls() {
+    let data_length: u32 = 42;
+    let chunk_type = "RuSt".as_bytes();
+    let message_bytes = "This is where your secret message will be!".as_bytes();
+    let crc: u32 = 2882656334;
+
+    let chunk_data: Vec<u8> = data_length
+      .to_be_bytes()
+      .iter()
+      .chain(chunk_type.iter())
+      .chain(message_bytes.iter())
+      .chain(crc.to_be_bytes().iter())
+      .copied()
+      .collect();
+
+    let chunk: Chunk = TryFrom::try_from(chunk_data.as_ref()).unwrap();
+
+    let _chunk_string = format!("{}", chunk);
+  }
+}
@@ -0,0 +1,229 @@
+use std::fmt::Display;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, crc::Crc32, png::Png, Error, Result};
+
+/// Number of bytes a chunk occupies besides its data: length, type and CRC.
+pub(crate) const OVERHEAD: usize = 12;
+
+/// A chunk borrowed from a byte buffer, e.g. a whole file read into memory or
+/// memory-mapped. Nothing is copied until it is turned into a [`Chunk`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkRef<'a> {
+  bytes: &'a [u8],
+  chunk_type: ChunkType,
+}
+
+/// Parses exactly one chunk, which must fill the whole slice, and verifies
+/// its CRC.
+impl<'a> TryFrom<&'a [u8]> for ChunkRef<'a> {
+  type Error = Error;
+  fn try_from(value: &'a [u8]) -> Result<Self> {
+    if value.len() < OVERHEAD {
+      return Err(Error::Truncated {
+        offset: 0,
+        needed: OVERHEAD,
+        available: value.len(),
+      });
+    }
+    let length = u32::from_be_bytes([value[0], value[1], value[2], value[3]]);
+    let actual = value.len() - OVERHEAD;
+    if (length as usize) > actual {
+      return Err(Error::Truncated {
+        offset: 0,
+        needed: OVERHEAD + length as usize,
+        available: value.len(),
+      });
+    }
+    if (length as usize) < actual {
+      return Err(Error::LengthMismatch {
+        offset: 0,
+        declared: length,
+        actual,
+      });
+    }
+    let chunk_type = ChunkType::try_from([value[4], value[5], value[6], value[7]])?;
+    let chunk = ChunkRef {
+      bytes: value,
+      chunk_type,
+    };
+
+    let mut crc = Crc32::new();
+    crc.update(&value[4..value.len() - 4]);
+    if crc.finish() != chunk.crc() {
+      return Err(Error::CrcMismatch {
+        offset: 0,
+        expected: crc.finish(),
+        found: chunk.crc(),
+      });
+    }
+    Ok(chunk)
+  }
+}
+
+impl From<ChunkRef<'_>> for Chunk {
+  fn from(value: ChunkRef<'_>) -> Self {
+    Chunk::from_parts(
+      value.length(),
+      value.chunk_type,
+      value.data().to_vec(),
+      value.crc(),
+    )
+  }
+}
+
+impl Display for ChunkRef<'_> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "ChunkRef {{ length: {}, type: {}, crc: {} }}",
+      self.length(),
+      self.chunk_type,
+      self.crc()
+    )
+  }
+}
+
+impl<'a> ChunkRef<'a> {
+  /// The length field, which parsing checked against the data.
+  pub fn length(&self) -> u32 {
+    u32::from_be_bytes([self.bytes[0], self.bytes[1], self.bytes[2], self.bytes[3]])
+  }
+
+  pub fn chunk_type(&self) -> ChunkType {
+    self.chunk_type
+  }
+
+  pub fn data(&self) -> &'a [u8] {
+    &self.bytes[8..self.bytes.len() - 4]
+  }
+
+  pub fn crc(&self) -> u32 {
+    let crc = &self.bytes[self.bytes.len() - 4..];
+    u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]])
+  }
+
+  /// The chunk exactly as it appears in the buffer.
+  pub fn as_bytes(&self) -> &'a [u8] {
+    self.bytes
+  }
+}
+
+/// Iterator over the chunks of a PNG held in memory. Stops after the first
+/// error.
+#[derive(Debug, Clone)]
+pub struct ChunkRefs<'a> {
+  bytes: &'a [u8],
+  offset: usize,
+}
+
+impl<'a> ChunkRefs<'a> {
+  /// Checks the PNG signature at the start of `bytes`.
+  pub fn new(bytes: &'a [u8]) -> Result<Self> {
+    if bytes.len() < Png::STANDARD_HEADER.len() || bytes[..8] != Png::STANDARD_HEADER {
+      return Err(Error::InvalidSignature);
+    }
+    Ok(ChunkRefs {
+      bytes,
+      offset: Png::STANDARD_HEADER.len(),
+    })
+  }
+
+  fn parse_next(&self) -> Result<ChunkRef<'a>> {
+    let rest = &self.bytes[self.offset..];
+    if rest.len() < 4 {
+      return Err(Error::Truncated {
+        offset: self.offset,
+        needed: 4,
+        available: rest.len(),
+      });
+    }
+    let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+    let end = rest.len().min(length.saturating_add(OVERHEAD));
+    ChunkRef::try_from(&rest[..end]).map_err(|e| e.offset_by(self.offset))
+  }
+}
+
+impl<'a> Iterator for ChunkRefs<'a> {
+  type Item = Result<ChunkRef<'a>>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.offset >= self.bytes.len() {
+      return None;
+    }
+    match self.parse_next() {
+      Ok(chunk) => {
+        self.offset += chunk.as_bytes().len();
+        Some(Ok(chunk))
+      }
+      Err(e) => {
+        self.offset = self.bytes.len();
+        Some(Err(e))
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::str::FromStr;
+
+  fn testing_png() -> Png {
+    Png::from_chunks(vec![
+      Chunk::new(
+        ChunkType::from_str("FrSt").unwrap(),
+        b"I am the first chunk".to_vec(),
+      ),
+      Chunk::new(
+        ChunkType::from_str("LASt").unwrap(),
+        b"I am the last chunk".to_vec(),
+      ),
+    ])
+  }
+
+  #[test]
+  fn test_chunk_ref_borrows_buffer() {
+    let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), b"borrowed".to_vec());
+    let bytes = chunk.as_bytes();
+    let chunk_ref = ChunkRef::try_from(bytes.as_slice()).unwrap();
+
+    assert_eq!(chunk_ref.length(), 8);
+    assert_eq!(chunk_ref.chunk_type().to_string(), "RuSt");
+    assert_eq!(chunk_ref.data(), b"borrowed");
+    assert_eq!(chunk_ref.crc(), chunk.crc());
+    assert_eq!(chunk_ref.as_bytes().as_ptr(), bytes.as_ptr());
+    assert_eq!(chunk_ref.data().as_ptr(), bytes[8..].as_ptr());
+  }
+
+  #[test]
+  fn test_into_owned_chunk() {
+    let bytes = testing_png().as_bytes();
+    let owned: Vec<Chunk> = ChunkRefs::new(&bytes)
+      .unwrap()
+      .map(|chunk| chunk.map(Chunk::from))
+      .collect::<Result<_>>()
+      .unwrap();
+
+    assert_eq!(owned.len(), 2);
+    assert_eq!(owned[1].data_as_string().unwrap(), "I am the last chunk");
+    assert_eq!(owned[1].as_bytes(), testing_png().chunks()[1].as_bytes());
+  }
+
+  #[test]
+  fn test_chunk_refs_errors() {
+    let mut bytes = testing_png().as_bytes();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    let results: Vec<Result<ChunkRef>> = ChunkRefs::new(&bytes).unwrap().collect();
+
+    assert_eq!(results.len(), 2);
+    assert!(matches!(
+      results[1],
+      Err(Error::CrcMismatch { offset: 40, .. })
+    ));
+    assert!(matches!(
+      ChunkRefs::new(&bytes[1..]),
+      Err(Error::InvalidSignature)
+    ));
+  }
+}
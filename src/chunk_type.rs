@@ -1,36 +1,36 @@
-use std::{
-  fmt::Display,
-  io::{Error as IoError, ErrorKind},
-  str::FromStr,
-};
+use std::{fmt::Display, str::FromStr};
 
 use crate::Error;
 
-#[derive(Debug, PartialEq, Eq)]
-struct ChunkType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChunkType {
   value: [u8; 4],
 }
 
+/// Strict: every byte must be an ASCII letter.
 impl TryFrom<[u8; 4]> for ChunkType {
   type Error = Error;
   fn try_from(value: [u8; 4]) -> Result<Self, Self::Error> {
-    Ok(ChunkType { value })
+    if let Some((index, &byte)) = value
+      .iter()
+      .enumerate()
+      .find(|(_, i)| !i.is_ascii_alphabetic())
+    {
+      return Err(Error::InvalidChunkTypeByte { byte, index });
+    }
+    Ok(ChunkType::from_bytes_lenient(value))
   }
 }
 
+/// Strict: the string must be exactly four ASCII letters.
 impl FromStr for ChunkType {
-  type Err = crate::Error;
+  type Err = Error;
   fn from_str(s: &str) -> Result<Self, Self::Err> {
-    if !s.chars().all(|i| i.is_ascii_alphabetic()) {
-      return Err(Box::new(IoError::new(
-        ErrorKind::InvalidInput,
-        "only ascii alphabets",
-      )));
-    }
-    let mut value = [0u8; 4];
-    value.copy_from_slice(&s.as_bytes()[..4]);
-
-    Ok(ChunkType { value })
+    let value: [u8; 4] = s
+      .as_bytes()
+      .try_into()
+      .map_err(|_| Error::InvalidChunkTypeLength(s.len()))?;
+    ChunkType::try_from(value)
   }
 }
 
@@ -49,34 +49,40 @@ impl Display for ChunkType {
 // impl Eq for ChunkType {}
 
 impl ChunkType {
-  fn bytes(&self) -> [u8; 4] {
+  /// Accepts any four bytes, for salvaging chunks out of damaged files.
+  /// Use [`ChunkType::try_from`] or [`str::parse`] for anything user-supplied.
+  pub fn from_bytes_lenient(value: [u8; 4]) -> Self {
+    ChunkType { value }
+  }
+
+  pub fn bytes(&self) -> [u8; 4] {
     self.value
   }
 
-  fn is_valid(&self) -> bool {
+  pub fn is_valid(&self) -> bool {
     // self.value[2] >= b'A' && self.value[2] <= b'Z'
-    u8::is_ascii_uppercase(&self.value[2]) && self.value.iter().all(|i| i.is_ascii_alphabetic())
+    self.is_reserved_bit_valid() && self.value.iter().all(|i| i.is_ascii_alphabetic())
   }
 
-  fn is_critical(&self) -> bool {
+  pub fn is_critical(&self) -> bool {
     // self.value[0] >= b'A' && self.value[0] <= b'Z'
 
     u8::is_ascii_uppercase(&self.value[0])
   }
 
-  fn is_public(&self) -> bool {
+  pub fn is_public(&self) -> bool {
     // self.value[1] >= b'A' && self.value[1] <= b'Z'
 
     u8::is_ascii_uppercase(&self.value[1])
   }
 
-  fn is_reserved_bit_valid(&self) -> bool {
+  pub fn is_reserved_bit_valid(&self) -> bool {
     // self.value[2] >= b'A' && self.value[2] <= b'Z'
 
     u8::is_ascii_uppercase(&self.value[2])
   }
 
-  fn is_safe_to_copy(&self) -> bool {
+  pub fn is_safe_to_copy(&self) -> bool {
     // !(self.value[3] >= b'A' && self.value[3] <= b'Z')
 
     !u8::is_ascii_uppercase(&self.value[3])
@@ -104,6 +110,37 @@ mod tests {
     assert_eq!(expected, actual);
   }
 
+  #[test]
+  pub fn test_chunk_type_from_str_wrong_length() {
+    assert!(matches!(
+      ChunkType::from_str("Ru"),
+      Err(Error::InvalidChunkTypeLength(2))
+    ));
+    assert!(matches!(
+      ChunkType::from_str("RuStY"),
+      Err(Error::InvalidChunkTypeLength(5))
+    ));
+    assert!(matches!(
+      ChunkType::from_str(""),
+      Err(Error::InvalidChunkTypeLength(0))
+    ));
+  }
+
+  #[test]
+  pub fn test_chunk_type_from_non_letter_bytes() {
+    assert!(matches!(
+      ChunkType::try_from([82, 117, 0, 116]),
+      Err(Error::InvalidChunkTypeByte { byte: 0, index: 2 })
+    ));
+  }
+
+  #[test]
+  pub fn test_chunk_type_from_bytes_lenient() {
+    let chunk = ChunkType::from_bytes_lenient([82, 117, 0, 116]);
+    assert_eq!(chunk.bytes(), [82, 117, 0, 116]);
+    assert!(!chunk.is_valid());
+  }
+
   #[test]
   pub fn test_chunk_type_is_critical() {
     let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -164,7 +201,13 @@ mod tests {
     assert!(!chunk.is_valid());
 
     let chunk = ChunkType::from_str("Ru1t");
-    assert!(chunk.is_err());
+    assert!(matches!(
+      chunk,
+      Err(Error::InvalidChunkTypeByte {
+        byte: b'1',
+        index: 2
+      })
+    ));
   }
 
   #[test]
@@ -1 +1,1157 @@
+use std::{
+  fs::{self, File},
+  io::{self, BufRead, BufReader, BufWriter, Write},
+  path::{Path, PathBuf},
+  str::FromStr,
+};
 
+use crate::{
+  args::{
+    Carrier, DecodeArgs, Decryption, EncodeArgs, Encryption, KeyInfoArgs, KeygenArgs,
+    ListMessagesArgs, Message, PrintArgs, RemoveArgs, VerifyArgs,
+  },
+  attachment::Attachment,
+  chunk::Chunk,
+  chunk_type::ChunkType,
+  compression::{self, Codec},
+  deflate::CompressionLevel,
+  encryption::{self, KdfParams},
+  envelope::{ContentType, Envelope},
+  filter::FilterStrategy,
+  fragment::{self, Fragment, HASH_SIZE},
+  image_header::ImageHeader,
+  keys::{PublicKey, SecretKey, SigningKey, VerifyingKey},
+  lsb,
+  manifest::{Entry, Manifest, MANIFEST_CHUNK_TYPE},
+  pixel_buffer::PixelBuffer,
+  png::Png,
+  reader::ChunkReader,
+  signature::{Signature, SIGNATURE_CHUNK_TYPE},
+  writer::{ChunkAction, StreamEditor},
+  Error, Result,
+};
+
+/// Inserts the message or file as a new chunk before `IEND`, or hides it in
+/// the pixel data, and writes the result to `output`, or back to the input
+/// file when no output is given. The message is optionally compressed, then
+/// encrypted with a password or recipient key if one is given, then wrapped
+/// in an [`Envelope`] recording what was done. With a fragment size, or
+/// when the message is too large for one chunk, it is split across several
+/// chunks of the same type. With a signing key a signature chunk follows
+/// the message chunks, and with a name the manifest is rewritten to list
+/// the message. Files without a valid IHDR are left alone.
+pub fn encode(args: EncodeArgs) -> Result<()> {
+  let output = args.output.as_ref().unwrap_or(&args.file_path);
+  let (content_type, mut payload) = match args.message {
+    Message::Text(text) => (ContentType::Text, text.into_bytes()),
+    Message::File(path) => (ContentType::File, Attachment::from_file(&path)?.as_bytes()),
+  };
+  let envelope = Envelope::new(content_type)
+    .compressed(args.compress)
+    .encrypted(args.encryption.is_some())
+    .checksum(true);
+  let mut associated_data = associated_data(&args.carrier).to_vec();
+  associated_data.extend_from_slice(&envelope.associated_data());
+
+  if args.compress {
+    payload = compression::compress(&payload, Codec::default(), CompressionLevel::default())?;
+  }
+  let payload = match &args.encryption {
+    None => payload,
+    Some(Encryption::Password(password)) => encryption::seal_with_password(
+      &payload,
+      &read_password(password)?,
+      &associated_data,
+      KdfParams::default(),
+    )?,
+    Some(Encryption::Recipient(path)) => {
+      let recipient: PublicKey = fs::read_to_string(path)?.parse()?;
+      encryption::seal_for_recipient(&payload, &recipient, &associated_data)?
+    }
+  };
+  let payload = envelope.payload(payload).as_bytes()?;
+
+  match &args.carrier {
+    Carrier::Chunk(chunk_type) => {
+      let chunk_type = ChunkType::from_str(chunk_type)?;
+      if !chunk_type.is_valid() {
+        return Err(Error::ReservedChunkType(chunk_type.to_string()));
+      }
+      let signature = match &args.sign_key {
+        Some(path) => {
+          let key: SigningKey = fs::read_to_string(path)?.parse()?;
+          Some(Signature::sign(&key, &chunk_type, &payload))
+        }
+        None => None,
+      };
+      let entry = match &args.name {
+        Some(name) => Some(Entry::new(name.clone(), chunk_type, &payload)?),
+        None => None,
+      };
+      let fragment_size = args.fragment_size.or_else(|| {
+        (payload.len() > Chunk::MAX_LENGTH).then_some(Chunk::MAX_LENGTH - fragment::HEADER_SIZE)
+      });
+      let chunks = match fragment_size {
+        Some(size) => fragment::split(&payload, size)?
+          .iter()
+          .map(|fragment| Chunk::try_new(chunk_type, fragment.as_bytes()))
+          .collect::<Result<Vec<_>>>()?,
+        None => vec![Chunk::try_new(chunk_type, payload)?],
+      };
+      read_image_header(&args.file_path)?;
+      let manifest = match entry {
+        Some(entry) => {
+          let mut manifest = read_manifest(&args.file_path)?;
+          manifest.insert(entry)?;
+          Some(manifest)
+        }
+        None => None,
+      };
+
+      let replace_manifest = manifest.is_some();
+      let mut editor = StreamEditor::new(move |chunk_type: &ChunkType| {
+        if replace_manifest && chunk_type.bytes() == MANIFEST_CHUNK_TYPE {
+          ChunkAction::Drop
+        } else {
+          ChunkAction::Keep
+        }
+      });
+      for chunk in chunks {
+        editor = editor.insert(chunk);
+      }
+      if let Some(signature) = signature {
+        editor = editor.insert(signature.to_chunk());
+      }
+      if let Some(manifest) = manifest {
+        editor = editor.insert(manifest.to_chunk());
+      }
+      rewrite_file(&args.file_path, output, editor)
+    }
+    Carrier::Named(_) => Err(Error::Usage(
+      "encode: give a chunk type and --name instead".into(),
+    )),
+    Carrier::Pixels(options) => {
+      let mut png = Png::try_from(fs::read(&args.file_path)?.as_slice())?;
+      let mut pixels = PixelBuffer::try_from(&png)?;
+      lsb::embed(&mut pixels, &payload, options)?;
+      png.encode_scanlines(
+        pixels.data(),
+        FilterStrategy::default(),
+        CompressionLevel::default(),
+      )?;
+      write_file(output, |writer| Ok(writer.write_all(&png.as_bytes())?))
+    }
+  }
+}
+
+/// Prints the message stored in the first chunk of the given type, under
+/// the given name, or hidden in the pixel data, decrypting it with a
+/// password or secret key and decompressing it if needed. Embedded files,
+/// and any message when an output path is given, are written to that path
+/// instead. What was found, and any signature on the chunk, is reported on
+/// standard error.
+pub fn decode(args: DecodeArgs) -> Result<()> {
+  let (data, carrier) = match args.carrier {
+    Carrier::Chunk(chunk_type) => {
+      let message = find_message(&args.file_path, &chunk_type, None)?;
+      (read_chunk_message(message)?, Carrier::Chunk(chunk_type))
+    }
+    Carrier::Named(name) => {
+      let entry = find_entry(&args.file_path, &name)?;
+      let message = find_named_message(&args.file_path, &entry)?;
+      let chunk_type = entry.chunk_type().to_string();
+      (read_chunk_message(message)?, Carrier::Chunk(chunk_type))
+    }
+    Carrier::Pixels(options) => {
+      let png = Png::try_from(fs::read(&args.file_path)?.as_slice())?;
+      let data = lsb::extract(&PixelBuffer::try_from(&png)?, &options)?;
+      (data, Carrier::Pixels(options))
+    }
+  };
+  let (message, content_type) = open_message(data, &carrier, args.decryption.as_ref())?;
+  match (content_type, &args.output) {
+    (ContentType::File, output) => {
+      let attachment = Attachment::try_from(message.as_slice())?;
+      let Some(output) = output else {
+        return Err(Error::OutputPathRequired {
+          name: attachment.name().to_string(),
+          mime_type: attachment.mime_type().to_string(),
+        });
+      };
+      write_file(output, |writer| Ok(writer.write_all(attachment.data())?))?;
+      eprintln!(
+        "wrote '{}' ({}, {} bytes) to {}",
+        attachment.name(),
+        attachment.mime_type(),
+        attachment.data().len(),
+        output.display()
+      );
+    }
+    (ContentType::Other(_), _) => {
+      return Err(Error::InvalidEnvelope(format!(
+        "cannot decode {}",
+        content_type
+      )))
+    }
+    (_, Some(output)) => write_file(output, |writer| Ok(writer.write_all(&message)?))?,
+    (ContentType::Text, None) => println!("{}", String::from_utf8(message)?),
+    (ContentType::Binary, None) => io::stdout().lock().write_all(&message)?,
+  }
+  Ok(())
+}
+
+/// Checks the signature on the first message of the given chunk type and
+/// prints who made it, optionally insisting on a particular signer.
+pub fn verify(args: VerifyArgs) -> Result<()> {
+  let message = find_message(&args.file_path, &args.chunk_type, None)?;
+  let data = message.payload()?;
+  let signature = message
+    .signature
+    .ok_or_else(|| Error::SignatureNotFound(args.chunk_type.clone()))?;
+  let fingerprint = signature.signer().fingerprint();
+  if let Some(path) = &args.signer {
+    let expected: VerifyingKey = fs::read_to_string(path)?.parse()?;
+    if *signature.signer() != expected {
+      return Err(Error::WrongSigner(fingerprint.to_string()));
+    }
+  }
+  signature.verify(&data)?;
+  println!("good signature by {}", fingerprint);
+  Ok(())
+}
+
+/// Removes the first message of the given chunk type, or the message with
+/// the given name, with all of its fragments, its signature and its
+/// manifest entry, and rewrites the file. Fragments are removed even when
+/// some are missing, and a name is removed even when its message is gone,
+/// so a damaged file can be cleaned up.
+pub fn remove(args: RemoveArgs) -> Result<()> {
+  let mut manifest = read_manifest(&args.file_path)?;
+  let (chunk_type, message, entry) = match &args.carrier {
+    Carrier::Chunk(chunk_type) => {
+      let message = find_message(&args.file_path, chunk_type, None)?;
+      let entry = manifest.remove_message(&ChunkType::from_str(chunk_type)?, &message.hash());
+      (chunk_type.clone(), Some(message), entry)
+    }
+    Carrier::Named(name) => {
+      let entry = manifest
+        .remove(name)
+        .ok_or_else(|| Error::MessageNotFound(name.clone()))?;
+      let message = match find_named_message(&args.file_path, &entry) {
+        Ok(message) => Some(message),
+        Err(Error::MessageNotFound(_)) => None,
+        Err(e) => return Err(e),
+      };
+      (entry.chunk_type().to_string(), message, Some(entry))
+    }
+    Carrier::Pixels(_) => {
+      return Err(Error::Usage(
+        "remove: messages hidden in the pixel data cannot be removed".into(),
+      ))
+    }
+  };
+
+  let chunk_indices = message
+    .as_ref()
+    .map_or(&[][..], |message| &message.chunk_indices);
+  let replace_manifest = entry.is_some();
+  let mut index = 0;
+  let mut editor = StreamEditor::new(|chunk_type: &ChunkType| {
+    let drop = chunk_indices.contains(&index)
+      || (replace_manifest && chunk_type.bytes() == MANIFEST_CHUNK_TYPE);
+    index += 1;
+    if drop {
+      ChunkAction::Drop
+    } else {
+      ChunkAction::Keep
+    }
+  });
+  if replace_manifest && !manifest.is_empty() {
+    editor = editor.insert(manifest.to_chunk());
+  }
+  rewrite_file(&args.file_path, &args.file_path, editor)?;
+
+  let mut removed = match &entry {
+    Some(entry) => format!("removed '{}' from {}", entry.name(), chunk_type),
+    None => format!("removed {}", chunk_type),
+  };
+  match &message {
+    Some(message) => {
+      if let Some(fragments) = message.fragment_count() {
+        removed += &format!(" ({} fragments)", fragments);
+      }
+      if message.signature.is_some() {
+        removed += " and its signature";
+      }
+    }
+    None => removed += ", its message was already gone",
+  }
+  println!("{}", removed);
+  Ok(())
+}
+
+/// Lists the named messages with their chunk type, stored size and how they
+/// are encoded. Nothing is decrypted, so no key is needed.
+pub fn list_messages(args: ListMessagesArgs) -> Result<()> {
+  let manifest = read_manifest(&args.file_path)?;
+  if manifest.is_empty() {
+    println!("no named messages");
+    return Ok(());
+  }
+  for entry in manifest.entries() {
+    let encoding = match find_named_message(&args.file_path, entry) {
+      Ok(message) => describe_message(&message).unwrap_or_else(|e| format!("unreadable: {}", e)),
+      Err(Error::MessageNotFound(_)) => "missing".to_string(),
+      Err(e) => return Err(e),
+    };
+    println!(
+      "{}\t{}\t{} bytes\t{}",
+      entry.name(),
+      entry.chunk_type(),
+      entry.size(),
+      encoding
+    );
+  }
+  Ok(())
+}
+
+/// Prints the image header and lists every chunk in the file.
+pub fn print_chunks(args: PrintArgs) -> Result<()> {
+  let mut reader = ChunkReader::new(BufReader::new(File::open(&args.file_path)?))?;
+  while let Some(header) = reader.next_header()? {
+    if header.chunk_type.bytes() == *b"IHDR" {
+      match ImageHeader::try_from(reader.read_data()?.as_slice()) {
+        Ok(image_header) => println!("{}", image_header),
+        Err(e) => println!("{}", e),
+      }
+    }
+    let chunk_type = &header.chunk_type;
+    println!(
+      "{} offset={} length={} (critical: {}, public: {}, safe to copy: {})",
+      chunk_type,
+      header.offset,
+      header.length,
+      chunk_type.is_critical(),
+      chunk_type.is_public(),
+      chunk_type.is_safe_to_copy()
+    );
+  }
+  Ok(())
+}
+
+/// Generates an X25519 key pair, or an Ed25519 one with `--signing`, and
+/// writes both halves, refusing to overwrite existing files.
+pub fn keygen(args: KeygenArgs) -> Result<()> {
+  let public_key_path = args.public_key_path.unwrap_or_else(|| {
+    let mut path = args.secret_key_path.clone().into_os_string();
+    path.push(".pub");
+    path.into()
+  });
+  let (secret, public, fingerprint) = if args.signing {
+    let signing = SigningKey::generate()?;
+    let verifying = signing.verifying_key();
+    (
+      signing.to_file_contents(),
+      verifying.to_file_contents(),
+      verifying.fingerprint(),
+    )
+  } else {
+    let secret = SecretKey::generate()?;
+    let public = secret.public_key();
+    (
+      secret.to_file_contents(),
+      public.to_file_contents(),
+      public.fingerprint(),
+    )
+  };
+
+  write_new_file(&args.secret_key_path, &secret, true)?;
+  write_new_file(&public_key_path, &public, false)?;
+  println!("secret key: {}", args.secret_key_path.display());
+  println!("public key: {}", public_key_path.display());
+  println!("fingerprint: {}", fingerprint);
+  Ok(())
+}
+
+/// Loads a key file and prints its kind and fingerprint.
+pub fn key_info(args: KeyInfoArgs) -> Result<()> {
+  let text = fs::read_to_string(&args.key_path)?;
+  let (kind, fingerprint) = if let Ok(public) = text.parse::<PublicKey>() {
+    ("x25519 public key", public.fingerprint())
+  } else if let Ok(verifying) = text.parse::<VerifyingKey>() {
+    ("ed25519 verifying key", verifying.fingerprint())
+  } else if let Ok(signing) = text.parse::<SigningKey>() {
+    ("ed25519 signing key", signing.verifying_key().fingerprint())
+  } else {
+    let secret: SecretKey = text.parse()?;
+    ("x25519 secret key", secret.public_key().fingerprint())
+  };
+  println!("{}, fingerprint {}", kind, fingerprint);
+  Ok(())
+}
+
+/// A message stored in one chunk or fragmented across several, as found in
+/// a file.
+struct StoredMessage {
+  whole: Option<Vec<u8>>,
+  fragments: Vec<Fragment>,
+  signature: Option<Signature>,
+  /// Positions in the file of every chunk making up the message, signature
+  /// included.
+  chunk_indices: Vec<usize>,
+}
+
+impl StoredMessage {
+  /// The stored payload, reassembled if it was fragmented.
+  fn payload(&self) -> Result<Vec<u8>> {
+    match &self.whole {
+      Some(data) => Ok(data.clone()),
+      None => fragment::reassemble(self.fragments.clone()),
+    }
+  }
+
+  fn fragment_count(&self) -> Option<usize> {
+    (!self.fragments.is_empty()).then_some(self.fragments.len())
+  }
+
+  /// The hash identifying the message, see [`fragment::payload_hash`].
+  fn hash(&self) -> [u8; HASH_SIZE] {
+    match (&self.whole, self.fragments.first()) {
+      (Some(data), _) => fragment::payload_hash(data),
+      (None, Some(fragment)) => fragment.hash(),
+      (None, None) => unreachable!("a found message has a payload or fragments"),
+    }
+  }
+}
+
+/// Finds the first message stored under `chunk_type`, or the one with
+/// `hash` if given. A message in a single chunk is signed by the first
+/// signature chunk naming that type before the next chunk of the type. A
+/// fragmented message collects every fragment with the same hash, wherever
+/// it is in the file, and is signed by the first signature chunk naming the
+/// type after its first fragment and before any other chunk of the type.
+fn find_message(
+  path: &Path,
+  chunk_type: &str,
+  hash: Option<&[u8; HASH_SIZE]>,
+) -> Result<StoredMessage> {
+  let chunk_type = ChunkType::from_str(chunk_type)?;
+  let mut reader = ChunkReader::new(BufReader::new(File::open(path)?))?;
+  let mut message = StoredMessage {
+    whole: None,
+    fragments: Vec::new(),
+    signature: None,
+    chunk_indices: Vec::new(),
+  };
+  let mut index = 0;
+  let mut signature_closed = false;
+  while let Some(header) = reader.next_header()? {
+    let position = index;
+    index += 1;
+    let found = message.whole.is_some() || !message.fragments.is_empty();
+
+    if header.chunk_type == chunk_type {
+      if message.whole.is_some() {
+        break;
+      }
+      let data = reader.read_data()?;
+      if Fragment::is_fragment(&data) {
+        let fragment = Fragment::try_from(data.as_slice())?;
+        let wanted = match message.fragments.first() {
+          Some(first) => first.hash() == fragment.hash(),
+          None => hash.is_none_or(|hash| fragment.hash() == *hash),
+        };
+        if wanted {
+          message.fragments.push(fragment);
+          message.chunk_indices.push(position);
+        } else if found {
+          signature_closed = true;
+        }
+      } else if !found && hash.is_none_or(|hash| fragment::payload_hash(&data) == *hash) {
+        message.whole = Some(data);
+        message.chunk_indices.push(position);
+      } else if found {
+        signature_closed = true;
+      }
+    } else if found
+      && !signature_closed
+      && message.signature.is_none()
+      && header.chunk_type.bytes() == SIGNATURE_CHUNK_TYPE
+    {
+      let signature = Signature::try_from(reader.read_data()?.as_slice())?;
+      if *signature.chunk_type() == chunk_type {
+        message.signature = Some(signature);
+        message.chunk_indices.push(position);
+      }
+    }
+  }
+
+  if message.chunk_indices.is_empty() {
+    return Err(Error::ChunkNotFound(chunk_type.to_string()));
+  }
+  Ok(message)
+}
+
+/// Finds the message a manifest entry names.
+fn find_named_message(path: &Path, entry: &Entry) -> Result<StoredMessage> {
+  let chunk_type = entry.chunk_type().to_string();
+  match find_message(path, &chunk_type, Some(&entry.hash())) {
+    Err(Error::ChunkNotFound(_)) => Err(Error::MessageNotFound(entry.name().to_string())),
+    result => result,
+  }
+}
+
+/// Reassembles a message found in chunks, reporting its fragments and
+/// checking its signature on standard error.
+fn read_chunk_message(message: StoredMessage) -> Result<Vec<u8>> {
+  let data = message.payload()?;
+  if let Some(fragments) = message.fragment_count() {
+    eprintln!("reassembled {} fragments", fragments);
+  }
+  if let Some(signature) = &message.signature {
+    match signature.verify(&data) {
+      Ok(()) => eprintln!("good signature by {}", signature.signer().fingerprint()),
+      Err(e) => eprintln!("warning: {}", e),
+    }
+  }
+  Ok(data)
+}
+
+/// Summarizes how a message is stored, e.g. `text, compressed, encrypted,
+/// 3 fragments, signed by 1a2b:3c4d:5e6f:7a8b`.
+fn describe_message(message: &StoredMessage) -> Result<String> {
+  let data = message.payload()?;
+  let mut parts = Vec::new();
+  if Envelope::is_envelope(&data) {
+    let envelope = Envelope::try_from(data.as_slice())?;
+    parts.push(envelope.content_type().to_string());
+    if envelope.is_compressed() {
+      parts.push("compressed".to_string());
+    }
+    if envelope.is_encrypted() {
+      parts.push("encrypted".to_string());
+    }
+  } else {
+    parts.push("bare payload".to_string());
+  }
+  if let Some(fragments) = message.fragment_count() {
+    parts.push(format!("{} fragments", fragments));
+  }
+  if let Some(signature) = &message.signature {
+    let fingerprint = signature.signer().fingerprint();
+    match signature.verify(&data) {
+      Ok(()) => parts.push(format!("signed by {}", fingerprint)),
+      Err(_) => parts.push(format!("bad signature by {}", fingerprint)),
+    }
+  }
+  Ok(parts.join(", "))
+}
+
+/// Reads the manifest, or returns an empty one if the file has none.
+fn read_manifest(path: &Path) -> Result<Manifest> {
+  let mut reader = ChunkReader::new(BufReader::new(File::open(path)?))?;
+  while let Some(header) = reader.next_header()? {
+    if header.chunk_type.bytes() == MANIFEST_CHUNK_TYPE {
+      return Manifest::try_from(reader.read_data()?.as_slice());
+    }
+  }
+  Ok(Manifest::default())
+}
+
+/// Looks up `name` in the manifest.
+fn find_entry(path: &Path, name: &str) -> Result<Entry> {
+  read_manifest(path)?
+    .remove(name)
+    .ok_or_else(|| Error::MessageNotFound(name.to_string()))
+}
+
+/// Unwraps the envelope around a stored message, or takes `data` as a bare
+/// payload from before envelopes existed, then decrypts and decompresses it.
+fn open_message(
+  data: Vec<u8>,
+  carrier: &Carrier,
+  decryption: Option<&Decryption>,
+) -> Result<(Vec<u8>, ContentType)> {
+  let mut associated_data = associated_data(carrier).to_vec();
+  if !Envelope::is_envelope(&data) {
+    eprintln!("found a bare payload without an envelope");
+    let payload = match decryption {
+      Some(decryption) => decrypt(&data, decryption, &associated_data)?,
+      None => data,
+    };
+    return Ok((compression::decompress(payload)?, ContentType::Text));
+  }
+
+  let envelope = Envelope::try_from(data.as_slice())?;
+  match encryption::recipient_fingerprint(envelope.payload_bytes()) {
+    Some(fingerprint) if envelope.is_encrypted() => {
+      eprintln!("found {}, for key {}", envelope, fingerprint)
+    }
+    _ => eprintln!("found {}", envelope),
+  }
+  associated_data.extend_from_slice(&envelope.associated_data());
+  let (compressed, content_type) = (envelope.is_compressed(), envelope.content_type());
+
+  let mut payload = match (envelope.is_encrypted(), decryption) {
+    (true, Some(decryption)) => decrypt(envelope.payload_bytes(), decryption, &associated_data)?,
+    (true, None) => {
+      return Err(Error::WrongDecryptionMethod(
+        "the message is encrypted, give --password or --key".into(),
+      ))
+    }
+    (false, Some(_)) => {
+      return Err(Error::WrongDecryptionMethod(
+        "the message is not encrypted, drop --password or --key".into(),
+      ))
+    }
+    (false, None) => envelope.into_payload(),
+  };
+  if compressed {
+    if compression::codec(&payload)?.is_none() {
+      return Err(Error::InvalidEnvelope(
+        "payload is marked compressed but has no compression header".to_string(),
+      ));
+    }
+    payload = compression::decompress(payload)?;
+  }
+  Ok((payload, content_type))
+}
+
+fn decrypt(payload: &[u8], decryption: &Decryption, associated_data: &[u8]) -> Result<Vec<u8>> {
+  match decryption {
+    Decryption::Password(password) => {
+      encryption::open_with_password(payload, &read_password(password)?, associated_data)
+    }
+    Decryption::SecretKey(path) => {
+      let key: SecretKey = fs::read_to_string(path)?.parse()?;
+      encryption::open_with_key(payload, &key, associated_data)
+    }
+  }
+}
+
+/// The bytes an encrypted payload is bound to: its chunk type, so that the
+/// payload cannot be moved to another chunk unnoticed.
+fn associated_data(carrier: &Carrier) -> &[u8] {
+  match carrier {
+    Carrier::Chunk(chunk_type) => chunk_type.as_bytes(),
+    Carrier::Named(_) | Carrier::Pixels(_) => &[],
+  }
+}
+
+/// Returns the password as given, or reads it from standard input for `-`.
+fn read_password(password: &str) -> Result<String> {
+  if password != "-" {
+    return Ok(password.to_string());
+  }
+  let mut line = String::new();
+  io::stdin().lock().read_line(&mut line)?;
+  Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Creates `path` with `contents`, failing if it already exists. Secret
+/// files are readable by their owner only where the platform supports it.
+fn write_new_file(path: &Path, contents: &str, secret: bool) -> Result<()> {
+  let mut options = fs::OpenOptions::new();
+  options.write(true).create_new(true);
+  #[cfg(unix)]
+  if secret {
+    use std::os::unix::fs::OpenOptionsExt;
+    options.mode(0o600);
+  }
+  #[cfg(not(unix))]
+  let _ = secret;
+  options.open(path)?.write_all(contents.as_bytes())?;
+  Ok(())
+}
+
+/// Reads and validates the IHDR chunk at the start of the file.
+fn read_image_header(path: &Path) -> Result<ImageHeader> {
+  let mut reader = ChunkReader::new(BufReader::new(File::open(path)?))?;
+  match reader.next_header()? {
+    Some(header) if header.chunk_type.bytes() == *b"IHDR" => {
+      ImageHeader::try_from(reader.read_data()?.as_slice())
+    }
+    Some(header) => Err(Error::InvalidImageHeader(format!(
+      "first chunk is {}, not IHDR",
+      header.chunk_type
+    ))),
+    None => Err(Error::ChunkNotFound("IHDR".to_string())),
+  }
+}
+
+/// Runs `editor` from `input` to `output`. `input` and `output` may be the
+/// same file.
+fn rewrite_file<F>(input: &Path, output: &Path, editor: StreamEditor<F>) -> Result<()>
+where
+  F: FnMut(&ChunkType) -> ChunkAction,
+{
+  let reader = BufReader::new(File::open(input)?);
+  write_file(output, |writer| {
+    editor.run(reader, writer)?;
+    Ok(())
+  })
+}
+
+/// Writes `output` through a temporary file next to it, so that a failed
+/// write leaves any existing file untouched.
+fn write_file<F>(output: &Path, write: F) -> Result<()>
+where
+  F: FnOnce(&mut BufWriter<File>) -> Result<()>,
+{
+  let mut temp = PathBuf::from(output).into_os_string();
+  temp.push(".pngme-tmp");
+  let temp = PathBuf::from(temp);
+
+  let result = (|| {
+    let mut writer = BufWriter::new(File::create(&temp)?);
+    write(&mut writer)?;
+    writer.flush()?;
+    fs::rename(&temp, output)?;
+    Ok(())
+  })();
+  if result.is_err() {
+    let _ = fs::remove_file(&temp);
+  }
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::image_header::{ColorType, InterlaceMethod};
+
+  /// A directory under the system temp directory, removed when dropped.
+  struct TempDir(PathBuf);
+
+  impl TempDir {
+    fn new(name: &str) -> Self {
+      let path = std::env::temp_dir().join(format!("pngme-{}-{}", std::process::id(), name));
+      let _ = fs::remove_dir_all(&path);
+      fs::create_dir_all(&path).unwrap();
+      TempDir(path)
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+      self.0.join(name)
+    }
+  }
+
+  impl Drop for TempDir {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(&self.0);
+    }
+  }
+
+  fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+    Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+  }
+
+  /// Writes a PNG with a valid 1x1 header and the given chunks before
+  /// `IEND`.
+  fn write_png(path: &Path, chunks: Vec<Chunk>) {
+    let header = ImageHeader {
+      width: 1,
+      height: 1,
+      bit_depth: 8,
+      color_type: ColorType::Grayscale,
+      compression_method: 0,
+      filter_method: 0,
+      interlace_method: InterlaceMethod::None,
+    };
+    let mut all = vec![header.to_chunk(), chunk("IDAT", b"pixels")];
+    all.extend(chunks);
+    all.push(chunk("IEND", b""));
+    fs::write(path, Png::from_chunks(all).as_bytes()).unwrap();
+  }
+
+  fn chunk_types(path: &Path) -> Vec<String> {
+    Png::try_from(fs::read(path).unwrap().as_slice())
+      .unwrap()
+      .chunks()
+      .iter()
+      .map(|chunk| chunk.chunk_type().to_string())
+      .collect()
+  }
+
+  fn encode_args(path: &Path, chunk_type: &str, text: &str) -> EncodeArgs {
+    EncodeArgs {
+      file_path: path.to_path_buf(),
+      carrier: Carrier::Chunk(chunk_type.to_string()),
+      message: Message::Text(text.to_string()),
+      output: None,
+      encryption: None,
+      compress: false,
+      sign_key: None,
+      fragment_size: None,
+      name: None,
+    }
+  }
+
+  /// Decodes the message from `carrier` through a file and returns it.
+  fn decode_text(dir: &TempDir, path: &Path, carrier: Carrier) -> Result<String> {
+    let output = dir.path("decoded.txt");
+    decode(DecodeArgs {
+      file_path: path.to_path_buf(),
+      carrier,
+      decryption: None,
+      output: Some(output.clone()),
+    })?;
+    Ok(fs::read_to_string(output)?)
+  }
+
+  fn remove_chunk_type(path: &Path, chunk_type: &str) -> Result<()> {
+    remove(RemoveArgs {
+      file_path: path.to_path_buf(),
+      carrier: Carrier::Chunk(chunk_type.to_string()),
+    })
+  }
+
+  fn encode_named(path: &Path, chunk_type: &str, text: &str, name: &str) -> Result<()> {
+    let mut args = encode_args(path, chunk_type, text);
+    args.name = Some(name.to_string());
+    encode(args)
+  }
+
+  fn remove_name(path: &Path, name: &str) -> Result<()> {
+    remove(RemoveArgs {
+      file_path: path.to_path_buf(),
+      carrier: Carrier::Named(name.to_string()),
+    })
+  }
+
+  fn names(path: &Path) -> Vec<String> {
+    let manifest = read_manifest(path).unwrap();
+    manifest
+      .entries()
+      .iter()
+      .map(|entry| entry.name().to_string())
+      .collect()
+  }
+
+  fn ru_st() -> Carrier {
+    Carrier::Chunk("ruSt".to_string())
+  }
+
+  /// Writes the signing key made from `seed` and its verifying key, and
+  /// returns their paths.
+  fn write_signing_key(dir: &TempDir, seed: u8) -> (PathBuf, PathBuf) {
+    let signing = SigningKey::from_bytes([seed; 32]);
+    let signing_path = dir.path(&format!("signing-{}.key", seed));
+    let verifying_path = dir.path(&format!("signing-{}.key.pub", seed));
+    fs::write(&signing_path, signing.to_file_contents()).unwrap();
+    fs::write(&verifying_path, signing.verifying_key().to_file_contents()).unwrap();
+    (signing_path, verifying_path)
+  }
+
+  fn verify_args(path: &Path, chunk_type: &str, signer: Option<&Path>) -> VerifyArgs {
+    VerifyArgs {
+      file_path: path.to_path_buf(),
+      chunk_type: chunk_type.to_string(),
+      signer: signer.map(Path::to_path_buf),
+    }
+  }
+
+  #[test]
+  fn test_encode_decode_round_trip() {
+    let dir = TempDir::new("round-trip");
+    let input = dir.path("in.png");
+    let output = dir.path("out.png");
+    write_png(&input, vec![]);
+
+    let mut args = encode_args(&input, "ruSt", "hello");
+    args.output = Some(output.clone());
+    args.compress = true;
+    encode(args).unwrap();
+    assert_eq!(chunk_types(&input), ["IHDR", "IDAT", "IEND"]);
+    assert_eq!(chunk_types(&output), ["IHDR", "IDAT", "ruSt", "IEND"]);
+    assert_eq!(decode_text(&dir, &output, ru_st()).unwrap(), "hello");
+
+    // Without an output path the input file is rewritten.
+    encode(encode_args(&input, "RuSt", "in place")).unwrap();
+    assert_eq!(chunk_types(&input), ["IHDR", "IDAT", "RuSt", "IEND"]);
+    let carrier = Carrier::Chunk("RuSt".to_string());
+    assert_eq!(decode_text(&dir, &input, carrier).unwrap(), "in place");
+  }
+
+  #[test]
+  fn test_decode_takes_the_first_message() {
+    let dir = TempDir::new("first-message");
+    let path = dir.path("image.png");
+    write_png(&path, vec![]);
+    encode(encode_args(&path, "ruSt", "first")).unwrap();
+    encode(encode_args(&path, "ruSt", "second")).unwrap();
+    assert_eq!(decode_text(&dir, &path, ru_st()).unwrap(), "first");
+  }
+
+  #[test]
+  fn test_decode_bare_payload() {
+    let dir = TempDir::new("bare-payload");
+    let path = dir.path("image.png");
+    write_png(&path, vec![chunk("ruSt", b"from before envelopes")]);
+    assert_eq!(
+      decode_text(&dir, &path, ru_st()).unwrap(),
+      "from before envelopes"
+    );
+  }
+
+  #[test]
+  fn test_remove() {
+    let dir = TempDir::new("remove");
+    let path = dir.path("image.png");
+    write_png(&path, vec![chunk("teXt", b"unrelated")]);
+    encode(encode_args(&path, "ruSt", "first")).unwrap();
+    encode(encode_args(&path, "ruSt", "second")).unwrap();
+
+    remove_chunk_type(&path, "ruSt").unwrap();
+    assert_eq!(chunk_types(&path), ["IHDR", "IDAT", "teXt", "ruSt", "IEND"]);
+    assert_eq!(decode_text(&dir, &path, ru_st()).unwrap(), "second");
+
+    remove_chunk_type(&path, "ruSt").unwrap();
+    assert_eq!(chunk_types(&path), ["IHDR", "IDAT", "teXt", "IEND"]);
+  }
+
+  #[test]
+  fn test_remove_missing_message() {
+    let dir = TempDir::new("remove-missing");
+    let path = dir.path("image.png");
+    write_png(&path, vec![chunk("teXt", b"unrelated")]);
+    let before = fs::read(&path).unwrap();
+
+    assert!(matches!(
+      remove_chunk_type(&path, "ruSt"),
+      Err(Error::ChunkNotFound(_))
+    ));
+    assert!(matches!(
+      decode_text(&dir, &path, ru_st()),
+      Err(Error::ChunkNotFound(_))
+    ));
+    assert!(remove_chunk_type(&path, "ru1t").is_err());
+    assert_eq!(fs::read(&path).unwrap(), before);
+  }
+
+  #[test]
+  fn test_encode_rejects_invalid_files() {
+    let dir = TempDir::new("invalid-files");
+    let path = dir.path("image.png");
+    fs::write(
+      &path,
+      Png::from_chunks(vec![chunk("IDAT", b"pixels"), chunk("IEND", b"")]).as_bytes(),
+    )
+    .unwrap();
+    let before = fs::read(&path).unwrap();
+    assert!(matches!(
+      encode(encode_args(&path, "ruSt", "hello")),
+      Err(Error::InvalidImageHeader(_))
+    ));
+    assert!(encode(encode_args(&path, "Rust", "hello")).is_err());
+    assert_eq!(fs::read(&path).unwrap(), before);
+    assert!(!dir.path("image.png.pngme-tmp").exists());
+  }
+
+  #[test]
+  fn test_signature_belongs_to_its_message() {
+    let dir = TempDir::new("signature-owner");
+    let path = dir.path("image.png");
+    let (alice, alice_public) = write_signing_key(&dir, 1);
+    let (_, bob_public) = write_signing_key(&dir, 2);
+    write_png(&path, vec![]);
+    encode(encode_args(&path, "ruSt", "unsigned")).unwrap();
+    let mut args = encode_args(&path, "teSt", "other type");
+    args.sign_key = Some(alice.clone());
+    encode(args).unwrap();
+    let mut args = encode_args(&path, "ruSt", "signed");
+    args.sign_key = Some(alice);
+    encode(args).unwrap();
+    assert_eq!(
+      chunk_types(&path),
+      ["IHDR", "IDAT", "ruSt", "teSt", "siGn", "ruSt", "siGn", "IEND"]
+    );
+
+    // The first signature names another type and the second follows the
+    // next ruSt message, so neither belongs to the first.
+    let message = find_message(&path, "ruSt", None).unwrap();
+    assert!(message.signature.is_none());
+    assert_eq!(message.chunk_indices, [2]);
+    assert!(matches!(
+      verify(verify_args(&path, "ruSt", None)),
+      Err(Error::SignatureNotFound(_))
+    ));
+    let message = find_message(&path, "teSt", None).unwrap();
+    assert!(message.signature.is_some());
+    assert_eq!(message.chunk_indices, [3, 4]);
+
+    remove_chunk_type(&path, "ruSt").unwrap();
+    let message = find_message(&path, "ruSt", None).unwrap();
+    assert_eq!(message.chunk_indices, [4, 5]);
+    verify(verify_args(&path, "ruSt", Some(&alice_public))).unwrap();
+    assert!(matches!(
+      verify(verify_args(&path, "ruSt", Some(&bob_public))),
+      Err(Error::WrongSigner(_))
+    ));
+  }
+
+  #[test]
+  fn test_remove_drops_the_signature() {
+    let dir = TempDir::new("remove-signature");
+    let path = dir.path("image.png");
+    let (key, _) = write_signing_key(&dir, 1);
+    write_png(&path, vec![]);
+    let mut args = encode_args(&path, "ruSt", "signed");
+    args.sign_key = Some(key.clone());
+    encode(args).unwrap();
+    let mut args = encode_args(&path, "teSt", "also signed");
+    args.sign_key = Some(key);
+    encode(args).unwrap();
+
+    remove_chunk_type(&path, "ruSt").unwrap();
+    assert_eq!(chunk_types(&path), ["IHDR", "IDAT", "teSt", "siGn", "IEND"]);
+    verify(verify_args(&path, "teSt", None)).unwrap();
+  }
+
+  #[test]
+  fn test_fragmented_round_trip() {
+    let dir = TempDir::new("fragments");
+    let path = dir.path("image.png");
+    write_png(&path, vec![]);
+    let mut args = encode_args(&path, "ruSt", "split across several chunks");
+    args.fragment_size = Some(16);
+    encode(args).unwrap();
+    let types = chunk_types(&path);
+    assert!(types.iter().filter(|t| *t == "ruSt").count() > 1);
+    assert_eq!(
+      decode_text(&dir, &path, ru_st()).unwrap(),
+      "split across several chunks"
+    );
+  }
+
+  #[test]
+  fn test_fragments_are_collected_by_hash() {
+    let dir = TempDir::new("fragment-hash");
+    let path = dir.path("image.png");
+    let first = fragment::split(b"first message", 5).unwrap();
+    let second = fragment::split(b"second message", 5).unwrap();
+    assert_eq!((first.len(), second.len()), (3, 3));
+    // Interleaved, with the first message's fragments out of order.
+    let order = [
+      &first[1], &second[0], &first[0], &second[1], &second[2], &first[2],
+    ];
+    write_png(
+      &path,
+      order
+        .iter()
+        .map(|fragment| chunk("ruSt", &fragment.as_bytes()))
+        .collect(),
+    );
+
+    let message = find_message(&path, "ruSt", None).unwrap();
+    assert_eq!(message.chunk_indices, [2, 4, 7]);
+    assert_eq!(message.payload().unwrap(), b"first message");
+    let message = find_message(&path, "ruSt", Some(&second[0].hash())).unwrap();
+    assert_eq!(message.chunk_indices, [3, 5, 6]);
+    assert_eq!(message.payload().unwrap(), b"second message");
+    assert_eq!(decode_text(&dir, &path, ru_st()).unwrap(), "first message");
+
+    remove_chunk_type(&path, "ruSt").unwrap();
+    assert_eq!(
+      chunk_types(&path),
+      ["IHDR", "IDAT", "ruSt", "ruSt", "ruSt", "IEND"]
+    );
+    assert_eq!(decode_text(&dir, &path, ru_st()).unwrap(), "second message");
+  }
+
+  #[test]
+  fn test_remove_signed_fragmented_message() {
+    let dir = TempDir::new("remove-fragments");
+    let path = dir.path("image.png");
+    let (key, _) = write_signing_key(&dir, 1);
+    write_png(&path, vec![]);
+    let mut args = encode_args(&path, "ruSt", "signed and split into fragments");
+    args.fragment_size = Some(16);
+    args.sign_key = Some(key);
+    encode(args).unwrap();
+    encode(encode_args(&path, "ruSt", "kept")).unwrap();
+    assert!(chunk_types(&path).contains(&"siGn".to_string()));
+
+    remove_chunk_type(&path, "ruSt").unwrap();
+    assert_eq!(chunk_types(&path), ["IHDR", "IDAT", "ruSt", "IEND"]);
+    assert_eq!(decode_text(&dir, &path, ru_st()).unwrap(), "kept");
+  }
+
+  #[test]
+  fn test_remove_by_name_keeps_other_messages() {
+    let dir = TempDir::new("remove-name");
+    let path = dir.path("image.png");
+    write_png(&path, vec![]);
+    encode_named(&path, "ruSt", "first", "one").unwrap();
+    encode_named(&path, "ruSt", "second", "two").unwrap();
+    assert_eq!(names(&path), ["one", "two"]);
+    let named = |name: &str| Carrier::Named(name.to_string());
+    assert_eq!(decode_text(&dir, &path, named("two")).unwrap(), "second");
+
+    remove_name(&path, "two").unwrap();
+    assert_eq!(names(&path), ["one"]);
+    assert_eq!(chunk_types(&path), ["IHDR", "IDAT", "ruSt", "maNf", "IEND"]);
+    assert_eq!(decode_text(&dir, &path, named("one")).unwrap(), "first");
+
+    remove_name(&path, "one").unwrap();
+    assert_eq!(chunk_types(&path), ["IHDR", "IDAT", "IEND"]);
+  }
+
+  #[test]
+  fn test_remove_by_chunk_type_drops_the_name() {
+    let dir = TempDir::new("remove-named-type");
+    let path = dir.path("image.png");
+    write_png(&path, vec![]);
+    encode(encode_args(&path, "ruSt", "unnamed")).unwrap();
+    encode_named(&path, "ruSt", "named", "kept").unwrap();
+    encode_named(&path, "teSt", "named too", "gone").unwrap();
+
+    remove_chunk_type(&path, "ruSt").unwrap();
+    assert_eq!(names(&path), ["kept", "gone"]);
+    remove_chunk_type(&path, "teSt").unwrap();
+    assert_eq!(names(&path), ["kept"]);
+    remove_chunk_type(&path, "ruSt").unwrap();
+    assert_eq!(chunk_types(&path), ["IHDR", "IDAT", "IEND"]);
+  }
+
+  #[test]
+  fn test_remove_name_of_missing_message() {
+    let dir = TempDir::new("remove-dangling-name");
+    let path = dir.path("image.png");
+    write_png(&path, vec![]);
+    encode_named(&path, "ruSt", "lost", "dangling").unwrap();
+    let png = Png::try_from(fs::read(&path).unwrap().as_slice()).unwrap();
+    let chunks = png
+      .chunks()
+      .iter()
+      .filter(|chunk| chunk.chunk_type().to_string() != "ruSt")
+      .cloned()
+      .collect();
+    fs::write(&path, Png::from_chunks(chunks).as_bytes()).unwrap();
+    assert!(matches!(
+      decode_text(&dir, &path, Carrier::Named("dangling".to_string())),
+      Err(Error::MessageNotFound(_))
+    ));
+
+    remove_name(&path, "dangling").unwrap();
+    assert_eq!(chunk_types(&path), ["IHDR", "IDAT", "IEND"]);
+  }
+
+  #[test]
+  fn test_names_are_unique() {
+    let dir = TempDir::new("unique-names");
+    let path = dir.path("image.png");
+    write_png(&path, vec![]);
+    encode_named(&path, "ruSt", "first", "note").unwrap();
+    let before = fs::read(&path).unwrap();
+    assert!(matches!(
+      encode_named(&path, "teSt", "second", "note"),
+      Err(Error::MessageNameTaken(_))
+    ));
+    assert!(matches!(
+      remove_name(&path, "other"),
+      Err(Error::MessageNotFound(_))
+    ));
+    assert_eq!(fs::read(&path).unwrap(), before);
+  }
+}
@@ -0,0 +1,166 @@
+//! Optional compression of message payloads.
+//!
+//! A compressed payload starts with a small header:
+//!
+//! | bytes | field                               |
+//! |-------|-------------------------------------|
+//! | 1     | marker, always `0`                  |
+//! | 1     | codec, `1` for zlib                 |
+//! | 4     | uncompressed length, big-endian     |
+//! | rest  | compressed data                     |
+//!
+//! Messages given on the command line cannot contain a NUL byte, and
+//! encrypted payloads start with their non-zero scheme byte, so anything
+//! without the marker is an uncompressed payload from before compression
+//! existed and is passed through unchanged.
+
+use std::fmt::Display;
+
+use crate::{
+  deflate::{self, CompressionLevel},
+  inflate::{self, DEFAULT_OUTPUT_LIMIT},
+  Error, Result,
+};
+
+const MARKER: u8 = 0;
+const HEADER_SIZE: usize = 1 + 1 + 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Codec {
+  #[default]
+  Zlib,
+}
+
+impl Codec {
+  fn id(self) -> u8 {
+    match self {
+      Codec::Zlib => 1,
+    }
+  }
+
+  fn from_id(id: u8) -> Option<Self> {
+    match id {
+      1 => Some(Codec::Zlib),
+      _ => None,
+    }
+  }
+}
+
+impl Display for Codec {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Codec::Zlib => write!(f, "zlib"),
+    }
+  }
+}
+
+/// Compresses `data` and prepends the header.
+pub fn compress(data: &[u8], codec: Codec, level: CompressionLevel) -> Result<Vec<u8>> {
+  let length = u32::try_from(data.len()).map_err(|_| Error::MessageTooLarge {
+    size: data.len(),
+    capacity: u32::MAX as usize,
+  })?;
+  let mut payload = vec![MARKER, codec.id()];
+  payload.extend_from_slice(&length.to_be_bytes());
+  match codec {
+    Codec::Zlib => payload.extend(deflate::zlib_compress(data, level)),
+  }
+  Ok(payload)
+}
+
+/// The codec a payload was compressed with, or `None` for an uncompressed
+/// payload.
+pub fn codec(payload: &[u8]) -> Result<Option<Codec>> {
+  match payload {
+    [MARKER, id, ..] => Codec::from_id(*id)
+      .map(Some)
+      .ok_or_else(|| Error::InvalidCompressedData(format!("unknown codec {}", id))),
+    _ => Ok(None),
+  }
+}
+
+/// Undoes [`compress`], returning uncompressed payloads as they are.
+pub fn decompress(payload: Vec<u8>) -> Result<Vec<u8>> {
+  let Some(codec) = codec(&payload)? else {
+    return Ok(payload);
+  };
+  if payload.len() < HEADER_SIZE {
+    return Err(Error::InvalidCompressedData(
+      "truncated payload header".to_string(),
+    ));
+  }
+  let length = u32::from_be_bytes(payload[2..HEADER_SIZE].try_into().unwrap()) as usize;
+  if length > DEFAULT_OUTPUT_LIMIT {
+    return Err(Error::OutputLimitExceeded(DEFAULT_OUTPUT_LIMIT));
+  }
+
+  let data = match codec {
+    Codec::Zlib => inflate::zlib_decompress(&payload[HEADER_SIZE..], length)?,
+  };
+  if data.len() != length {
+    return Err(Error::InvalidCompressedData(format!(
+      "expected {} bytes, got {}",
+      length,
+      data.len()
+    )));
+  }
+  Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_round_trip() {
+    let message = "all work and no play makes jack a dull boy. ".repeat(50);
+    let payload = compress(message.as_bytes(), Codec::Zlib, CompressionLevel::default()).unwrap();
+    assert!(payload.len() < message.len() / 4);
+    assert_eq!(codec(&payload).unwrap(), Some(Codec::Zlib));
+    assert_eq!(decompress(payload).unwrap(), message.as_bytes());
+  }
+
+  #[test]
+  fn test_empty_message() {
+    let payload = compress(b"", Codec::Zlib, CompressionLevel::default()).unwrap();
+    assert_eq!(decompress(payload).unwrap(), b"");
+  }
+
+  #[test]
+  fn test_uncompressed_payloads_pass_through() {
+    for payload in [&b""[..], b"plain text", &[1, 0, 0], &[2]] {
+      assert_eq!(codec(payload).unwrap(), None);
+      assert_eq!(decompress(payload.to_vec()).unwrap(), payload);
+    }
+  }
+
+  #[test]
+  fn test_malformed_payloads() {
+    let payload = compress(
+      b"hello hello hello",
+      Codec::Zlib,
+      CompressionLevel::default(),
+    )
+    .unwrap();
+
+    let mut unknown = payload.clone();
+    unknown[1] = 9;
+    assert!(matches!(
+      decompress(unknown),
+      Err(Error::InvalidCompressedData(_))
+    ));
+
+    assert!(decompress(payload[..4].to_vec()).is_err());
+
+    let mut short = payload.clone();
+    short[5] -= 1;
+    assert!(decompress(short).is_err());
+
+    let mut long = payload;
+    long[5] += 1;
+    assert!(matches!(
+      decompress(long),
+      Err(Error::InvalidCompressedData(_))
+    ));
+  }
+}
@@ -0,0 +1,76 @@
+/// CRC-32 as used by PNG (ISO 3309 / ITU-T V.42, polynomial `0xEDB88320`).
+const TABLE: [u32; 256] = make_table();
+
+const fn make_table() -> [u32; 256] {
+  let mut table = [0u32; 256];
+  let mut n = 0;
+  while n < 256 {
+    let mut c = n as u32;
+    let mut k = 0;
+    while k < 8 {
+      if c & 1 == 1 {
+        c = 0xEDB8_8320 ^ (c >> 1);
+      } else {
+        c >>= 1;
+      }
+      k += 1;
+    }
+    table[n] = c;
+    n += 1;
+  }
+  table
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Crc32 {
+  value: u32,
+}
+
+impl Default for Crc32 {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Crc32 {
+  pub fn new() -> Self {
+    Crc32 { value: 0xFFFF_FFFF }
+  }
+
+  pub fn update(&mut self, bytes: &[u8]) {
+    let mut c = self.value;
+    for &b in bytes {
+      c = TABLE[((c ^ b as u32) & 0xFF) as usize] ^ (c >> 8);
+    }
+    self.value = c;
+  }
+
+  pub fn finish(&self) -> u32 {
+    self.value ^ 0xFFFF_FFFF
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn checksum(bytes: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(bytes);
+    crc.finish()
+  }
+
+  #[test]
+  pub fn test_checksum() {
+    assert_eq!(checksum(b"123456789"), 0xCBF4_3926);
+    assert_eq!(checksum(b"IEND"), 0xAE42_6082);
+  }
+
+  #[test]
+  pub fn test_incremental() {
+    let mut crc = Crc32::new();
+    crc.update(b"1234");
+    crc.update(b"56789");
+    assert_eq!(crc.finish(), checksum(b"123456789"));
+  }
+}
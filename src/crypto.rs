@@ -0,0 +1,130 @@
+//! Thin wrappers over the RustCrypto crates, so the rest of the crate deals
+//! in plain byte arrays. Nothing here is meant for use outside the crate;
+//! the public entry points live in [`crate::encryption`], and key pairs in
+//! [`crate::keys`].
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use blake2::{digest::consts::U32, Blake2b, Digest};
+use chacha20poly1305::{
+  aead::{Aead, Payload},
+  ChaCha20Poly1305, Key, KeyInit, Nonce,
+};
+
+use crate::{Error, Result};
+
+pub(crate) const KEY_SIZE: usize = 32;
+pub(crate) const NONCE_SIZE: usize = 12;
+pub(crate) const TAG_SIZE: usize = 16;
+
+/// Fills `buffer` from the operating system's random source, on every
+/// platform `getrandom` supports.
+pub(crate) fn random_bytes(buffer: &mut [u8]) -> Result<()> {
+  getrandom::fill(buffer).map_err(std::io::Error::from)?;
+  Ok(())
+}
+
+/// Compares two byte strings without exiting early on the first difference.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// BLAKE2b with a 32-byte output over `parts` concatenated.
+pub(crate) fn blake2b_256(parts: &[&[u8]]) -> [u8; 32] {
+  let mut hasher = Blake2b::<U32>::new();
+  for part in parts {
+    hasher.update(part);
+  }
+  hasher.finalize().into()
+}
+
+/// Argon2id, version 0x13, filling `out` from `password` and `salt`.
+pub(crate) fn argon2id(
+  password: &[u8],
+  salt: &[u8],
+  memory_kib: u32,
+  iterations: u32,
+  lanes: u32,
+  out: &mut [u8],
+) -> Result<()> {
+  let unsupported = |e: argon2::Error| {
+    Error::CorruptCiphertext(format!("unsupported key derivation parameters: {}", e))
+  };
+  let params = Params::new(memory_kib, iterations, lanes, Some(out.len())).map_err(unsupported)?;
+  Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+    .hash_password_into(password, salt, out)
+    .map_err(unsupported)
+}
+
+/// ChaCha20-Poly1305: encrypts `plaintext` and appends the 16-byte tag,
+/// which also covers `associated_data`.
+pub(crate) fn seal(
+  key: &[u8; KEY_SIZE],
+  nonce: &[u8; NONCE_SIZE],
+  associated_data: &[u8],
+  plaintext: &[u8],
+) -> Result<Vec<u8>> {
+  ChaCha20Poly1305::new(Key::from_slice(key))
+    .encrypt(
+      Nonce::from_slice(nonce),
+      Payload {
+        msg: plaintext,
+        aad: associated_data,
+      },
+    )
+    .map_err(|_| Error::MessageTooLarge {
+      size: plaintext.len(),
+      capacity: (1 << 38) - 64,
+    })
+}
+
+/// Checks the tag and decrypts, or returns `None` if anything was altered.
+pub(crate) fn open(
+  key: &[u8; KEY_SIZE],
+  nonce: &[u8; NONCE_SIZE],
+  associated_data: &[u8],
+  sealed: &[u8],
+) -> Option<Vec<u8>> {
+  ChaCha20Poly1305::new(Key::from_slice(key))
+    .decrypt(
+      Nonce::from_slice(nonce),
+      Payload {
+        msg: sealed,
+        aad: associated_data,
+      },
+    )
+    .ok()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  pub(crate) fn hex(text: &str) -> Vec<u8> {
+    let text: Vec<u8> = text.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    text
+      .chunks(2)
+      .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+      .collect()
+  }
+
+  #[test]
+  fn test_random_bytes_differ() {
+    let mut a = [0u8; 32];
+    let mut b = [0u8; 32];
+    super::random_bytes(&mut a).unwrap();
+    super::random_bytes(&mut b).unwrap();
+    assert_ne!(a, b);
+  }
+
+  #[test]
+  fn test_open_rejects_tampering() {
+    let key = [7u8; 32];
+    let nonce = [9u8; 12];
+    let sealed = super::seal(&key, &nonce, b"ad", b"hello").unwrap();
+    for i in 0..sealed.len() {
+      let mut altered = sealed.clone();
+      altered[i] ^= 0x80;
+      assert!(super::open(&key, &nonce, b"ad", &altered).is_none());
+    }
+    assert!(super::open(&key, &nonce, b"other", &sealed).is_none());
+    assert!(super::open(&key, &nonce, b"ad", &sealed[..10]).is_none());
+  }
+}
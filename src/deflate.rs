@@ -0,0 +1,677 @@
+//! zlib (RFC 1950) and DEFLATE (RFC 1951) compression, for writing IDAT.
+
+use crate::{
+  adler32::Adler32,
+  inflate::{CODE_LENGTH_ORDER, DIST_BASE, DIST_EXTRA, LENGTH_BASE, LENGTH_EXTRA},
+};
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+/// Tokens collected before a block is emitted.
+const BLOCK_TOKENS: usize = 16 * 1024;
+const MAX_STORED_BLOCK: usize = 65535;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompressionLevel {
+  /// No compression, only stored blocks.
+  Stored,
+  /// Short match searches.
+  Fast,
+  /// Long match searches with lazy matching.
+  #[default]
+  Best,
+}
+
+impl CompressionLevel {
+  /// How many earlier positions to try per match search, and whether to
+  /// defer a match by one byte when the next position matches longer.
+  fn search(&self) -> (usize, bool) {
+    match self {
+      CompressionLevel::Stored => (0, false),
+      CompressionLevel::Fast => (8, false),
+      CompressionLevel::Best => (1024, true),
+    }
+  }
+
+  /// The FLEVEL bits of the zlib header.
+  fn zlib_level(&self) -> u8 {
+    match self {
+      CompressionLevel::Stored => 0,
+      CompressionLevel::Fast => 1,
+      CompressionLevel::Best => 3,
+    }
+  }
+}
+
+/// Compresses `data` into a zlib stream.
+pub fn zlib_compress(data: &[u8], level: CompressionLevel) -> Vec<u8> {
+  let cmf = 0x78u8;
+  let mut flg = level.zlib_level() << 6;
+  flg += 31 - ((cmf as u16 * 256 + flg as u16) % 31) as u8;
+
+  let mut output = vec![cmf, flg];
+  output.extend(deflate(data, level));
+  let mut adler = Adler32::new();
+  adler.update(data);
+  output.extend_from_slice(&adler.finish().to_be_bytes());
+  output
+}
+
+/// Compresses `data` into a raw DEFLATE stream.
+pub fn deflate(data: &[u8], level: CompressionLevel) -> Vec<u8> {
+  let mut writer = BitWriter::default();
+  if level == CompressionLevel::Stored {
+    write_stored(&mut writer, data, true);
+    return writer.finish();
+  }
+
+  let (chain, lazy) = level.search();
+  let mut matcher = Matcher::new(data, chain, lazy);
+  let mut tokens = Vec::with_capacity(BLOCK_TOKENS);
+  let mut block_start = 0;
+  loop {
+    let block_end = matcher.collect(&mut tokens, BLOCK_TOKENS);
+    let last = block_end == data.len();
+    write_block(&mut writer, &tokens, &data[block_start..block_end], last);
+    if last {
+      return writer.finish();
+    }
+    tokens.clear();
+    block_start = block_end;
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Token {
+  Literal(u8),
+  Match { length: u16, distance: u16 },
+}
+
+/// LZ77 match finder using hash chains over three-byte prefixes.
+struct Matcher<'a> {
+  data: &'a [u8],
+  position: usize,
+  head: Vec<u32>,
+  prev: Vec<u32>,
+  chain: usize,
+  lazy: bool,
+}
+
+impl<'a> Matcher<'a> {
+  fn new(data: &'a [u8], chain: usize, lazy: bool) -> Self {
+    Matcher {
+      data,
+      position: 0,
+      head: vec![u32::MAX; 1 << HASH_BITS],
+      prev: vec![u32::MAX; WINDOW_SIZE],
+      chain,
+      lazy,
+    }
+  }
+
+  /// Appends up to `max` tokens and returns the input position reached.
+  fn collect(&mut self, tokens: &mut Vec<Token>, max: usize) -> usize {
+    let data = self.data;
+    while self.position < data.len() && tokens.len() < max {
+      let (length, distance) = self.find_match(self.position);
+      if length < MIN_MATCH {
+        tokens.push(Token::Literal(data[self.position]));
+        self.insert(self.position);
+        self.position += 1;
+        continue;
+      }
+      if self.lazy && length < MAX_MATCH {
+        self.insert(self.position);
+        let (next_length, _) = self.find_match(self.position + 1);
+        if next_length > length {
+          tokens.push(Token::Literal(data[self.position]));
+          self.position += 1;
+          continue;
+        }
+        for position in self.position + 1..self.position + length {
+          self.insert(position);
+        }
+      } else {
+        for position in self.position..self.position + length {
+          self.insert(position);
+        }
+      }
+      tokens.push(Token::Match {
+        length: length as u16,
+        distance: distance as u16,
+      });
+      self.position += length;
+    }
+    self.position
+  }
+
+  fn hash(&self, position: usize) -> Option<usize> {
+    let bytes = self.data.get(position..position + MIN_MATCH)?;
+    let value = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+    Some((value.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize)
+  }
+
+  fn insert(&mut self, position: usize) {
+    if let Some(hash) = self.hash(position) {
+      self.prev[position % WINDOW_SIZE] = self.head[hash];
+      self.head[hash] = position as u32;
+    }
+  }
+
+  /// Longest earlier match for the bytes at `position`, as (length, distance).
+  fn find_match(&self, position: usize) -> (usize, usize) {
+    let Some(hash) = self.hash(position) else {
+      return (0, 0);
+    };
+    let data = self.data;
+    let max_length = MAX_MATCH.min(data.len() - position);
+    let mut best = (0, 0);
+    let mut candidate = self.head[hash];
+    for _ in 0..self.chain {
+      if candidate == u32::MAX {
+        break;
+      }
+      let start = candidate as usize;
+      let distance = position - start;
+      if distance == 0 || distance > WINDOW_SIZE {
+        break;
+      }
+      if data[start + best.0] == data[position + best.0] {
+        let length = data[start..start + max_length]
+          .iter()
+          .zip(&data[position..position + max_length])
+          .take_while(|(a, b)| a == b)
+          .count();
+        if length > best.0 {
+          best = (length, distance);
+          if length == max_length {
+            break;
+          }
+        }
+      }
+      let next = self.prev[start % WINDOW_SIZE];
+      if next == u32::MAX || next as usize >= start {
+        break;
+      }
+      candidate = next;
+    }
+    best
+  }
+}
+
+/// Writes `tokens` (which encode `raw`) as whichever block type is smallest.
+fn write_block(writer: &mut BitWriter, tokens: &[Token], raw: &[u8], last: bool) {
+  let mut literal_freqs = [0u32; 286];
+  let mut distance_freqs = [0u32; 30];
+  for token in tokens {
+    match *token {
+      Token::Literal(byte) => literal_freqs[byte as usize] += 1,
+      Token::Match { length, distance } => {
+        literal_freqs[257 + length_code(length as usize)] += 1;
+        distance_freqs[distance_code(distance as usize)] += 1;
+      }
+    }
+  }
+  literal_freqs[256] = 1;
+
+  let literal_lengths = code_lengths(&literal_freqs, 15);
+  let distance_lengths = code_lengths(&distance_freqs, 15);
+  let header = DynamicHeader::new(&literal_lengths, &distance_lengths);
+
+  let (fixed_literals, fixed_distances) = fixed_lengths();
+  let dynamic_cost = 3
+    + header.cost()
+    + data_cost(
+      &literal_freqs,
+      &distance_freqs,
+      &literal_lengths,
+      &distance_lengths,
+    );
+  let fixed_cost = 3
+    + data_cost(
+      &literal_freqs,
+      &distance_freqs,
+      &fixed_literals,
+      &fixed_distances,
+    );
+  let stored_cost = (raw.len() + raw.len().div_ceil(MAX_STORED_BLOCK).max(1) * 5) * 8 + 7;
+
+  if stored_cost <= dynamic_cost.min(fixed_cost) {
+    write_stored(writer, raw, last);
+  } else if fixed_cost <= dynamic_cost {
+    writer.write(last as u32, 1);
+    writer.write(1, 2);
+    write_tokens(writer, tokens, &fixed_literals, &fixed_distances);
+  } else {
+    writer.write(last as u32, 1);
+    writer.write(2, 2);
+    header.write(writer);
+    write_tokens(writer, tokens, &literal_lengths, &distance_lengths);
+  }
+}
+
+fn write_stored(writer: &mut BitWriter, raw: &[u8], last: bool) {
+  let mut blocks = raw.chunks(MAX_STORED_BLOCK).peekable();
+  if blocks.peek().is_none() {
+    writer.write(last as u32, 1);
+    writer.write(0, 2);
+    writer.align_to_byte();
+    writer.write(0, 16);
+    writer.write(0xFFFF, 16);
+    return;
+  }
+  while let Some(block) = blocks.next() {
+    writer.write((last && blocks.peek().is_none()) as u32, 1);
+    writer.write(0, 2);
+    writer.align_to_byte();
+    writer.write(block.len() as u32, 16);
+    writer.write(!(block.len() as u16) as u32, 16);
+    writer.write_bytes(block);
+  }
+}
+
+fn write_tokens(
+  writer: &mut BitWriter,
+  tokens: &[Token],
+  literal_lengths: &[u8],
+  distance_lengths: &[u8],
+) {
+  let literal_codes = canonical_codes(literal_lengths);
+  let distance_codes = canonical_codes(distance_lengths);
+  for token in tokens {
+    match *token {
+      Token::Literal(byte) => writer.write_symbol(&literal_codes, literal_lengths, byte as usize),
+      Token::Match { length, distance } => {
+        let (length, distance) = (length as usize, distance as usize);
+        let code = length_code(length);
+        writer.write_symbol(&literal_codes, literal_lengths, 257 + code);
+        let extra = LENGTH_EXTRA[code] as u32;
+        writer.write((length - LENGTH_BASE[code] as usize) as u32, extra);
+
+        let code = distance_code(distance);
+        writer.write_symbol(&distance_codes, distance_lengths, code);
+        let extra = DIST_EXTRA[code] as u32;
+        writer.write((distance - DIST_BASE[code] as usize) as u32, extra);
+      }
+    }
+  }
+  writer.write_symbol(&literal_codes, literal_lengths, 256);
+}
+
+fn data_cost(
+  literal_freqs: &[u32],
+  distance_freqs: &[u32],
+  literal_lengths: &[u8],
+  distance_lengths: &[u8],
+) -> usize {
+  let mut bits = 0;
+  for (symbol, &freq) in literal_freqs.iter().enumerate() {
+    let extra = if symbol > 256 {
+      LENGTH_EXTRA[symbol - 257] as usize
+    } else {
+      0
+    };
+    bits += freq as usize * (literal_lengths[symbol] as usize + extra);
+  }
+  for (symbol, &freq) in distance_freqs.iter().enumerate() {
+    bits += freq as usize * (distance_lengths[symbol] as usize + DIST_EXTRA[symbol] as usize);
+  }
+  bits
+}
+
+fn length_code(length: usize) -> usize {
+  LENGTH_BASE.partition_point(|&base| base as usize <= length) - 1
+}
+
+fn distance_code(distance: usize) -> usize {
+  DIST_BASE.partition_point(|&base| base as usize <= distance) - 1
+}
+
+fn fixed_lengths() -> ([u8; 288], [u8; 30]) {
+  // All 288 lengths take part in assigning the canonical codes, even though
+  // symbols 286 and 287 never occur.
+  let mut literals = [0u8; 288];
+  literals[..144].fill(8);
+  literals[144..256].fill(9);
+  literals[256..280].fill(7);
+  literals[280..].fill(8);
+  (literals, [5; 30])
+}
+
+/// The code length definitions at the start of a dynamic block, run-length
+/// encoded with symbols 16, 17 and 18.
+struct DynamicHeader {
+  literal_count: usize,
+  distance_count: usize,
+  code_length_count: usize,
+  symbols: Vec<(u8, u8)>,
+  lengths: Vec<u8>,
+}
+
+impl DynamicHeader {
+  fn new(literal_lengths: &[u8], distance_lengths: &[u8]) -> Self {
+    let literal_count = 257.max(literal_lengths.len() - trailing_zeros(literal_lengths));
+    let distance_count = 1.max(distance_lengths.len() - trailing_zeros(distance_lengths));
+    let all: Vec<u8> = literal_lengths[..literal_count]
+      .iter()
+      .chain(&distance_lengths[..distance_count])
+      .copied()
+      .collect();
+
+    let mut symbols = Vec::new();
+    let mut i = 0;
+    while i < all.len() {
+      let value = all[i];
+      let run = all[i..].iter().take_while(|&&v| v == value).count();
+      if value == 0 && run >= 11 {
+        let run = run.min(138);
+        symbols.push((18, (run - 11) as u8));
+        i += run;
+      } else if value == 0 && run >= 3 {
+        symbols.push((17, (run - 3) as u8));
+        i += run;
+      } else if value != 0 && run >= 4 {
+        symbols.push((value, 0));
+        let run = (run - 1).min(6);
+        symbols.push((16, (run - 3) as u8));
+        i += run + 1;
+      } else {
+        symbols.push((value, 0));
+        i += 1;
+      }
+    }
+
+    let mut freqs = [0u32; 19];
+    for &(symbol, _) in &symbols {
+      freqs[symbol as usize] += 1;
+    }
+    let lengths = code_lengths(&freqs, 7);
+    let code_length_count = 4.max(
+      CODE_LENGTH_ORDER.len()
+        - CODE_LENGTH_ORDER
+          .iter()
+          .rev()
+          .take_while(|&&index| lengths[index] == 0)
+          .count(),
+    );
+
+    DynamicHeader {
+      literal_count,
+      distance_count,
+      code_length_count,
+      symbols,
+      lengths,
+    }
+  }
+
+  fn cost(&self) -> usize {
+    let symbols: usize = self
+      .symbols
+      .iter()
+      .map(|&(symbol, _)| self.lengths[symbol as usize] as usize + extra_bits(symbol) as usize)
+      .sum();
+    14 + 3 * self.code_length_count + symbols
+  }
+
+  fn write(&self, writer: &mut BitWriter) {
+    writer.write((self.literal_count - 257) as u32, 5);
+    writer.write((self.distance_count - 1) as u32, 5);
+    writer.write((self.code_length_count - 4) as u32, 4);
+    for &index in &CODE_LENGTH_ORDER[..self.code_length_count] {
+      writer.write(self.lengths[index] as u32, 3);
+    }
+    let codes = canonical_codes(&self.lengths);
+    for &(symbol, extra) in &self.symbols {
+      writer.write(
+        codes[symbol as usize] as u32,
+        self.lengths[symbol as usize] as u32,
+      );
+      writer.write(extra as u32, extra_bits(symbol));
+    }
+  }
+}
+
+fn extra_bits(code_length_symbol: u8) -> u32 {
+  match code_length_symbol {
+    16 => 2,
+    17 => 3,
+    18 => 7,
+    _ => 0,
+  }
+}
+
+fn trailing_zeros(lengths: &[u8]) -> usize {
+  lengths
+    .iter()
+    .rev()
+    .take_while(|&&length| length == 0)
+    .count()
+}
+
+/// Huffman code lengths for `freqs`, limited to `max_length` bits. At least
+/// two symbols always get a code, since some decoders reject codes with a
+/// single symbol.
+fn code_lengths(freqs: &[u32], max_length: usize) -> Vec<u8> {
+  let mut freqs = freqs.to_vec();
+  for symbol in 0..freqs.len() {
+    if freqs.iter().filter(|&&freq| freq > 0).count() >= 2 {
+      break;
+    }
+    if freqs[symbol] == 0 {
+      freqs[symbol] = 1;
+    }
+  }
+
+  // Build the tree bottom-up; each node remembers its parent so leaf depths
+  // can be read off afterwards.
+  let mut symbols: Vec<usize> = (0..freqs.len()).filter(|&s| freqs[s] > 0).collect();
+  symbols.sort_by_key(|&s| (freqs[s], s));
+  let leaves = symbols.len();
+  let mut weight: Vec<u64> = symbols.iter().map(|&s| freqs[s] as u64).collect();
+  let mut parent = vec![usize::MAX; 2 * leaves - 1];
+  let (mut next_leaf, mut next_node) = (0, leaves);
+  weight.resize(2 * leaves - 1, 0);
+  for node in leaves..2 * leaves - 1 {
+    let mut pick = || {
+      if next_leaf < leaves && (next_node >= node || weight[next_leaf] <= weight[next_node]) {
+        next_leaf += 1;
+        next_leaf - 1
+      } else {
+        next_node += 1;
+        next_node - 1
+      }
+    };
+    let (a, b) = (pick(), pick());
+    weight[node] = weight[a] + weight[b];
+    parent[a] = node;
+    parent[b] = node;
+  }
+
+  let mut depth = vec![0usize; 2 * leaves - 1];
+  for node in (0..2 * leaves - 2).rev() {
+    depth[node] = depth[parent[node]] + 1;
+  }
+
+  // Limit the depth by moving codes between length counts until the Kraft
+  // sum is exactly one again.
+  let mut counts = vec![0usize; max_length + 1];
+  for &d in &depth[..leaves] {
+    counts[d.min(max_length)] += 1;
+  }
+  let mut total: usize = (1..=max_length)
+    .map(|l| counts[l] << (max_length - l))
+    .sum();
+  while total > 1 << max_length {
+    counts[max_length] -= 1;
+    for length in (1..max_length).rev() {
+      if counts[length] > 0 {
+        counts[length] -= 1;
+        counts[length + 1] += 2;
+        break;
+      }
+    }
+    total -= 1;
+  }
+
+  // Hand out the lengths, shortest to the most frequent symbols.
+  let mut lengths = vec![0u8; freqs.len()];
+  let mut by_frequency = symbols.iter().rev();
+  for (length, &count) in counts.iter().enumerate().skip(1) {
+    for _ in 0..count {
+      lengths[*by_frequency.next().unwrap()] = length as u8;
+    }
+  }
+  lengths
+}
+
+/// Canonical codes for `lengths`, bit-reversed so they can be written least
+/// significant bit first.
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+  let mut counts = [0u16; 16];
+  for &length in lengths {
+    counts[length as usize] += 1;
+  }
+  counts[0] = 0;
+  let mut next_code = [0u16; 16];
+  let mut code = 0u16;
+  for bits in 1..16 {
+    code = (code + counts[bits - 1]) << 1;
+    next_code[bits] = code;
+  }
+  lengths
+    .iter()
+    .map(|&length| {
+      if length == 0 {
+        return 0;
+      }
+      let code = next_code[length as usize];
+      next_code[length as usize] += 1;
+      code.reverse_bits() >> (16 - length)
+    })
+    .collect()
+}
+
+/// Writes bits least significant first, as DEFLATE packs them.
+#[derive(Default)]
+struct BitWriter {
+  output: Vec<u8>,
+  buffer: u64,
+  count: u32,
+}
+
+impl BitWriter {
+  fn write(&mut self, value: u32, bits: u32) {
+    self.buffer |= (value as u64) << self.count;
+    self.count += bits;
+    while self.count >= 8 {
+      self.output.push(self.buffer as u8);
+      self.buffer >>= 8;
+      self.count -= 8;
+    }
+  }
+
+  fn write_symbol(&mut self, codes: &[u16], lengths: &[u8], symbol: usize) {
+    self.write(codes[symbol] as u32, lengths[symbol] as u32);
+  }
+
+  fn align_to_byte(&mut self) {
+    if self.count > 0 {
+      self.write(0, 8 - self.count);
+    }
+  }
+
+  /// Writes whole bytes; the writer must be byte-aligned.
+  fn write_bytes(&mut self, bytes: &[u8]) {
+    self.output.extend_from_slice(bytes);
+  }
+
+  fn finish(mut self) -> Vec<u8> {
+    self.align_to_byte();
+    self.output
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::inflate::{inflate, zlib_decompress, DEFAULT_OUTPUT_LIMIT};
+
+  const LEVELS: [CompressionLevel; 3] = [
+    CompressionLevel::Stored,
+    CompressionLevel::Fast,
+    CompressionLevel::Best,
+  ];
+
+  fn samples() -> Vec<Vec<u8>> {
+    let mut state = 0x1234_5678u32;
+    let noise: Vec<u8> = (0..100_000)
+      .map(|_| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state as u8
+      })
+      .collect();
+    let text: Vec<u8> = (0..5000)
+      .flat_map(|i| format!("row {} value {}\n", i % 97, i % 13).into_bytes())
+      .collect();
+    vec![
+      Vec::new(),
+      vec![42],
+      vec![0; 300_000],
+      b"abcabcabcabcabcabcabcabcabcabc".to_vec(),
+      noise,
+      text,
+    ]
+  }
+
+  #[test]
+  fn test_round_trip() {
+    for data in samples() {
+      for level in LEVELS {
+        let compressed = zlib_compress(&data, level);
+        let decompressed = zlib_decompress(&compressed, DEFAULT_OUTPUT_LIMIT).unwrap();
+        assert_eq!(decompressed, data, "{:?} {}", level, data.len());
+
+        let raw = deflate(&data, level);
+        assert_eq!(inflate(&raw, DEFAULT_OUTPUT_LIMIT).unwrap(), data);
+      }
+    }
+  }
+
+  #[test]
+  fn test_levels_compress() {
+    let data = &samples()[5];
+    let stored = zlib_compress(data, CompressionLevel::Stored).len();
+    let fast = zlib_compress(data, CompressionLevel::Fast).len();
+    let best = zlib_compress(data, CompressionLevel::Best).len();
+    assert!(stored > data.len());
+    assert!(fast < data.len() / 4);
+    assert!(best <= fast);
+  }
+
+  #[test]
+  fn test_zlib_header() {
+    for level in LEVELS {
+      let compressed = zlib_compress(b"x", level);
+      assert_eq!(compressed[0], 0x78);
+      assert_eq!((compressed[0] as u16 * 256 + compressed[1] as u16) % 31, 0);
+    }
+  }
+
+  #[test]
+  fn test_code_lengths_are_limited() {
+    // Fibonacci frequencies give the deepest possible Huffman tree.
+    let mut freqs = vec![1u32, 1];
+    while freqs.len() < 30 {
+      let next = freqs[freqs.len() - 1] + freqs[freqs.len() - 2];
+      freqs.push(next);
+    }
+    let lengths = code_lengths(&freqs, 15);
+    assert!(lengths.iter().all(|&length| (1..=15).contains(&length)));
+    let kraft: f64 = lengths.iter().map(|&l| 0.5f64.powi(l as i32)).sum();
+    assert!((kraft - 1.0).abs() < 1e-9);
+  }
+}
@@ -0,0 +1,245 @@
+use std::str::FromStr;
+
+use crate::{
+  chunk::Chunk,
+  chunk_type::ChunkType,
+  deflate::CompressionLevel,
+  filter::{self, FilterStrategy},
+  image_header::{ColorType, ImageHeader, InterlaceMethod},
+  pixel_buffer::{PixelBuffer, Transparency},
+  png::{Png, DEFAULT_IDAT_SIZE},
+  Error, Result,
+};
+
+/// Builds a complete PNG (IHDR, optional PLTE and tRNS, IDAT, IEND) from a
+/// [`PixelBuffer`].
+///
+/// ```
+/// use rust_pngme::{encoder::Encoder, ColorType, PixelBuffer};
+///
+/// let pixels = PixelBuffer::new(2, 1, ColorType::Rgb, 8, vec![255, 0, 0, 0, 0, 255])?;
+/// let png = Encoder::new().encode(&pixels)?;
+/// assert_eq!(PixelBuffer::try_from(&png)?, pixels);
+/// # Ok::<(), rust_pngme::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct Encoder {
+  interlace_method: InterlaceMethod,
+  filter_strategy: FilterStrategy,
+  compression_level: CompressionLevel,
+  max_idat_size: usize,
+}
+
+impl Default for Encoder {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Encoder {
+  pub fn new() -> Self {
+    Encoder {
+      interlace_method: InterlaceMethod::None,
+      filter_strategy: FilterStrategy::default(),
+      compression_level: CompressionLevel::default(),
+      max_idat_size: DEFAULT_IDAT_SIZE,
+    }
+  }
+
+  pub fn interlace_method(mut self, interlace_method: InterlaceMethod) -> Self {
+    self.interlace_method = interlace_method;
+    self
+  }
+
+  pub fn filter_strategy(mut self, filter_strategy: FilterStrategy) -> Self {
+    self.filter_strategy = filter_strategy;
+    self
+  }
+
+  pub fn compression_level(mut self, compression_level: CompressionLevel) -> Self {
+    self.compression_level = compression_level;
+    self
+  }
+
+  pub fn max_idat_size(mut self, max_idat_size: usize) -> Self {
+    self.max_idat_size = max_idat_size;
+    self
+  }
+
+  pub fn encode(&self, pixels: &PixelBuffer) -> Result<Png> {
+    let header = ImageHeader {
+      width: pixels.width(),
+      height: pixels.height(),
+      bit_depth: pixels.bit_depth(),
+      color_type: pixels.color_type(),
+      compression_method: 0,
+      filter_method: 0,
+      interlace_method: self.interlace_method,
+    };
+    header.validate()?;
+
+    let mut png = Png::from_chunks(vec![header.to_chunk()]);
+    match (pixels.color_type(), pixels.palette()) {
+      (ColorType::Indexed, None) => {
+        return Err(Error::InvalidImageData(
+          "indexed images need a palette".to_string(),
+        ))
+      }
+      (ColorType::Grayscale | ColorType::GrayscaleAlpha, Some(_)) => {
+        return Err(Error::InvalidImageData(
+          "grayscale images cannot have a palette".to_string(),
+        ))
+      }
+      (ColorType::Indexed, Some(palette)) if palette.len() > 1 << pixels.bit_depth() => {
+        return Err(Error::InvalidImageData(format!(
+          "{} palette entries do not fit in {} bits",
+          palette.len(),
+          pixels.bit_depth()
+        )))
+      }
+      (_, Some(palette)) => png.append_chunk(chunk("PLTE", palette.concat())),
+      (_, None) => {}
+    }
+
+    if let Some(transparency) = pixels.transparency() {
+      check_transparency(transparency, pixels)?;
+      png.append_chunk(chunk("tRNS", transparency.to_bytes()));
+    }
+
+    let filtered = filter::filter_image(pixels.data(), &header, self.filter_strategy)?;
+    png.append_chunk(chunk("IEND", Vec::new()));
+    png.set_image_data(&filtered, self.compression_level, self.max_idat_size);
+    Ok(png)
+  }
+}
+
+fn check_transparency(transparency: &Transparency, pixels: &PixelBuffer) -> Result<()> {
+  let max = (1u32 << pixels.bit_depth()) - 1;
+  let valid = match (transparency, pixels.color_type()) {
+    (Transparency::Grayscale(gray), ColorType::Grayscale) => *gray as u32 <= max,
+    (Transparency::Rgb(r, g, b), ColorType::Rgb) => [r, g, b].iter().all(|&&s| s as u32 <= max),
+    (Transparency::Palette(alpha), ColorType::Indexed) => {
+      alpha.len() <= pixels.palette().map_or(0, |palette| palette.len())
+    }
+    _ => false,
+  };
+  if !valid {
+    return Err(Error::InvalidImageData(format!(
+      "transparency {:?} does not fit {}-bit {} images",
+      transparency,
+      pixels.bit_depth(),
+      pixels.color_type()
+    )));
+  }
+  Ok(())
+}
+
+fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+  Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn pixels(width: u32, height: u32, color_type: ColorType, bit_depth: u8) -> PixelBuffer {
+    let row_bits = width as usize * color_type.channels() * bit_depth as usize;
+    let row_bytes = row_bits.div_ceil(8);
+    // Indexed samples stay small so they fall inside any test palette, and
+    // padding bits stay zero because Adam7 does not carry them through.
+    let mask = if color_type == ColorType::Indexed {
+      0x11
+    } else {
+      0xFF
+    };
+    let padding_mask = match row_bits % 8 {
+      0 => 0xFF,
+      used => !(0xFFu8 >> used),
+    };
+    let data = (0..row_bytes * height as usize)
+      .map(|i| {
+        let byte = (i * 31 + i / 7) as u8 & mask;
+        if i % row_bytes == row_bytes - 1 {
+          byte & padding_mask
+        } else {
+          byte
+        }
+      })
+      .collect();
+    PixelBuffer::new(width, height, color_type, bit_depth, data).unwrap()
+  }
+
+  fn round_trip(encoder: &Encoder, pixels: &PixelBuffer) {
+    let png = encoder.encode(pixels).unwrap();
+    let bytes = png.as_bytes();
+    let decoded = PixelBuffer::try_from(&Png::try_from(bytes.as_slice()).unwrap()).unwrap();
+    assert_eq!(&decoded, pixels);
+  }
+
+  #[test]
+  fn test_round_trip_all_formats() {
+    let formats = [
+      (ColorType::Grayscale, &[1, 2, 4, 8, 16][..]),
+      (ColorType::Rgb, &[8, 16][..]),
+      (ColorType::GrayscaleAlpha, &[8, 16][..]),
+      (ColorType::Rgba, &[8, 16][..]),
+    ];
+    for interlace in [InterlaceMethod::None, InterlaceMethod::Adam7] {
+      let encoder = Encoder::new().interlace_method(interlace);
+      for (color_type, depths) in formats {
+        for &depth in depths {
+          round_trip(&encoder, &pixels(11, 7, color_type, depth));
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn test_palette_and_transparency() {
+    let mut indexed = pixels(9, 4, ColorType::Indexed, 8);
+    indexed
+      .set_palette((0..=255).map(|i| [i, 255 - i, i / 2]).collect())
+      .unwrap();
+    indexed.set_transparency(Some(Transparency::Palette(vec![0, 64, 128])));
+    round_trip(&Encoder::new(), &indexed);
+
+    let mut gray = pixels(4, 4, ColorType::Grayscale, 4);
+    gray.set_transparency(Some(Transparency::Grayscale(3)));
+    round_trip(&Encoder::new(), &gray);
+  }
+
+  #[test]
+  fn test_chunk_layout() {
+    let png = Encoder::new()
+      .compression_level(CompressionLevel::Stored)
+      .max_idat_size(100)
+      .encode(&pixels(16, 16, ColorType::Rgb, 8))
+      .unwrap();
+    let types: Vec<String> = png
+      .chunks()
+      .iter()
+      .map(|chunk| chunk.chunk_type().to_string())
+      .collect();
+    assert_eq!(types.first().unwrap(), "IHDR");
+    assert_eq!(types.last().unwrap(), "IEND");
+    assert_eq!(types.iter().filter(|t| *t == "IDAT").count(), 8);
+  }
+
+  #[test]
+  fn test_invalid_combinations() {
+    let indexed = pixels(2, 2, ColorType::Indexed, 1);
+    assert!(Encoder::new().encode(&indexed).is_err());
+
+    let mut gray = pixels(2, 2, ColorType::Grayscale, 8);
+    gray.set_palette(vec![[0, 0, 0]]).unwrap();
+    assert!(Encoder::new().encode(&gray).is_err());
+
+    let mut rgba = pixels(2, 2, ColorType::Rgba, 8);
+    rgba.set_transparency(Some(Transparency::Rgb(0, 0, 0)));
+    assert!(Encoder::new().encode(&rgba).is_err());
+
+    let mut gray = pixels(2, 2, ColorType::Grayscale, 2);
+    gray.set_transparency(Some(Transparency::Grayscale(4)));
+    assert!(Encoder::new().encode(&gray).is_err());
+  }
+}
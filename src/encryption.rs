@@ -0,0 +1,438 @@
+//! Authenticated encryption of message payloads.
+//!
+//! A password-sealed payload is laid out as:
+//!
+//! | bytes | field                                         |
+//! |-------|-----------------------------------------------|
+//! | 1     | scheme, `1` for password                      |
+//! | 4     | Argon2id memory in KiB, big-endian            |
+//! | 4     | Argon2id iterations, big-endian               |
+//! | 1     | Argon2id lanes                                |
+//! | 16    | salt                                          |
+//! | 12    | nonce                                         |
+//! | 16    | password check                                |
+//! | rest  | ChaCha20-Poly1305 ciphertext and 16-byte tag  |
+//!
+//! Argon2id stretches the password into a 32-byte key and a 16-byte check
+//! value. The check value tells a wrong password apart from a payload that
+//! was altered after sealing, which only the AEAD tag can detect.
+//!
+//! A payload sealed for a recipient's X25519 public key is laid out as:
+//!
+//! | bytes | field                                         |
+//! |-------|-----------------------------------------------|
+//! | 1     | scheme, `2` for recipient                     |
+//! | 8     | recipient key fingerprint                     |
+//! | 32    | ephemeral public key                          |
+//! | 12    | nonce                                         |
+//! | rest  | ChaCha20-Poly1305 ciphertext and 16-byte tag  |
+//!
+//! The key is BLAKE2b over the shared secret and both public keys. The
+//! fingerprint tells a message meant for another key apart from corruption.
+//!
+//! In both schemes everything before the ciphertext, plus the caller's
+//! associated data, is authenticated by the tag.
+
+use crate::{
+  crypto::{self, NONCE_SIZE, TAG_SIZE},
+  keys::{Fingerprint, PublicKey, SecretKey},
+  Error, Result,
+};
+
+const SCHEME_PASSWORD: u8 = 1;
+const SCHEME_RECIPIENT: u8 = 2;
+const SALT_SIZE: usize = 16;
+const CHECK_SIZE: usize = 16;
+const PASSWORD_HEADER_SIZE: usize = 1 + 4 + 4 + 1 + SALT_SIZE + NONCE_SIZE + CHECK_SIZE;
+const RECIPIENT_HEADER_SIZE: usize = 1 + Fingerprint::SIZE + 32 + NONCE_SIZE;
+
+/// Argon2id cost parameters stored with each payload. Decoding refuses
+/// anything above [`KdfParams::MAX_MEMORY_KIB`] and
+/// [`KdfParams::MAX_ITERATIONS`], so a crafted chunk cannot make it allocate
+/// or spin without bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+  pub memory_kib: u32,
+  pub iterations: u32,
+  pub lanes: u8,
+}
+
+impl Default for KdfParams {
+  /// 19 MiB, two passes, one lane: the OWASP baseline for Argon2id.
+  fn default() -> Self {
+    KdfParams {
+      memory_kib: 19 * 1024,
+      iterations: 2,
+      lanes: 1,
+    }
+  }
+}
+
+impl KdfParams {
+  pub const MAX_MEMORY_KIB: u32 = 1024 * 1024;
+  pub const MAX_ITERATIONS: u32 = 64;
+
+  fn validate(&self) -> Result<()> {
+    let lanes = self.lanes as u32;
+    if lanes == 0
+      || self.iterations == 0
+      || self.memory_kib < 8 * lanes
+      || self.memory_kib > Self::MAX_MEMORY_KIB
+      || self.iterations > Self::MAX_ITERATIONS
+    {
+      return Err(Error::CorruptCiphertext(format!(
+        "unsupported key derivation parameters {:?}",
+        self
+      )));
+    }
+    Ok(())
+  }
+
+  fn derive(&self, password: &[u8], salt: &[u8]) -> Result<([u8; 32], [u8; CHECK_SIZE])> {
+    let mut output = [0u8; 32 + CHECK_SIZE];
+    crypto::argon2id(
+      password,
+      salt,
+      self.memory_kib,
+      self.iterations,
+      self.lanes as u32,
+      &mut output,
+    )?;
+    Ok((
+      output[..32].try_into().unwrap(),
+      output[32..].try_into().unwrap(),
+    ))
+  }
+}
+
+/// Encrypts `plaintext` under a key derived from `password` with a fresh
+/// random salt and nonce. `associated_data` is authenticated but not stored;
+/// the same bytes must be passed to [`open_with_password`].
+pub fn seal_with_password(
+  plaintext: &[u8],
+  password: &str,
+  associated_data: &[u8],
+  params: KdfParams,
+) -> Result<Vec<u8>> {
+  params.validate()?;
+  let mut salt = [0u8; SALT_SIZE];
+  let mut nonce = [0u8; NONCE_SIZE];
+  crypto::random_bytes(&mut salt)?;
+  crypto::random_bytes(&mut nonce)?;
+  let (key, check) = params.derive(password.as_bytes(), &salt)?;
+
+  let mut sealed = Vec::with_capacity(PASSWORD_HEADER_SIZE + plaintext.len() + 16);
+  sealed.push(SCHEME_PASSWORD);
+  sealed.extend_from_slice(&params.memory_kib.to_be_bytes());
+  sealed.extend_from_slice(&params.iterations.to_be_bytes());
+  sealed.push(params.lanes);
+  sealed.extend_from_slice(&salt);
+  sealed.extend_from_slice(&nonce);
+  sealed.extend_from_slice(&check);
+
+  let aad = [associated_data, &sealed].concat();
+  sealed.extend(crypto::seal(&key, &nonce, &aad, plaintext)?);
+  Ok(sealed)
+}
+
+/// Reverses [`seal_with_password`]. Fails with [`Error::WrongPassword`] when
+/// the password does not match and [`Error::CorruptCiphertext`] when the
+/// payload is malformed or was altered.
+pub fn open_with_password(
+  sealed: &[u8],
+  password: &str,
+  associated_data: &[u8],
+) -> Result<Vec<u8>> {
+  check_scheme(sealed, SCHEME_PASSWORD, PASSWORD_HEADER_SIZE)?;
+
+  let (header, ciphertext) = sealed.split_at(PASSWORD_HEADER_SIZE);
+  let params = KdfParams {
+    memory_kib: u32::from_be_bytes(header[1..5].try_into().unwrap()),
+    iterations: u32::from_be_bytes(header[5..9].try_into().unwrap()),
+    lanes: header[9],
+  };
+  params.validate()?;
+  let salt = &header[10..10 + SALT_SIZE];
+  let nonce: [u8; NONCE_SIZE] = header[10 + SALT_SIZE..][..NONCE_SIZE].try_into().unwrap();
+  let stored_check = &header[PASSWORD_HEADER_SIZE - CHECK_SIZE..];
+
+  let (key, check) = params.derive(password.as_bytes(), salt)?;
+  if !crypto::constant_time_eq(&check, stored_check) {
+    return Err(Error::WrongPassword);
+  }
+  let aad = [associated_data, header].concat();
+  crypto::open(&key, &nonce, &aad, ciphertext).ok_or_else(|| {
+    Error::CorruptCiphertext("authentication failed, the message was altered".to_string())
+  })
+}
+
+/// Encrypts `plaintext` so that only the holder of the secret key behind
+/// `recipient` can read it, using a fresh ephemeral key pair.
+/// `associated_data` works as in [`seal_with_password`].
+pub fn seal_for_recipient(
+  plaintext: &[u8],
+  recipient: &PublicKey,
+  associated_data: &[u8],
+) -> Result<Vec<u8>> {
+  let ephemeral = SecretKey::generate()?;
+  let ephemeral_public = ephemeral.public_key();
+  let shared = ephemeral.diffie_hellman(recipient);
+  if shared == [0; 32] {
+    return Err(Error::InvalidKey(
+      "recipient key is a low-order point".to_string(),
+    ));
+  }
+  let mut nonce = [0u8; NONCE_SIZE];
+  crypto::random_bytes(&mut nonce)?;
+  let key = recipient_key(&shared, &ephemeral_public, recipient);
+
+  let mut sealed = Vec::with_capacity(RECIPIENT_HEADER_SIZE + plaintext.len() + 16);
+  sealed.push(SCHEME_RECIPIENT);
+  sealed.extend_from_slice(&recipient.fingerprint().bytes());
+  sealed.extend_from_slice(&ephemeral_public.bytes());
+  sealed.extend_from_slice(&nonce);
+
+  let aad = [associated_data, &sealed].concat();
+  sealed.extend(crypto::seal(&key, &nonce, &aad, plaintext)?);
+  Ok(sealed)
+}
+
+/// Reverses [`seal_for_recipient`]. Fails with [`Error::WrongKey`] when the
+/// message was sealed for a different key and [`Error::CorruptCiphertext`]
+/// when the payload is malformed or was altered.
+pub fn open_with_key(sealed: &[u8], key: &SecretKey, associated_data: &[u8]) -> Result<Vec<u8>> {
+  check_scheme(sealed, SCHEME_RECIPIENT, RECIPIENT_HEADER_SIZE)?;
+  let recipient = key.public_key();
+  let fingerprint = recipient_fingerprint(sealed).unwrap();
+  if fingerprint != recipient.fingerprint() {
+    return Err(Error::WrongKey(fingerprint.to_string()));
+  }
+
+  let (header, ciphertext) = sealed.split_at(RECIPIENT_HEADER_SIZE);
+  let ephemeral_public =
+    PublicKey::from_bytes(header[1 + Fingerprint::SIZE..][..32].try_into().unwrap());
+  let nonce: [u8; NONCE_SIZE] = header[RECIPIENT_HEADER_SIZE - NONCE_SIZE..]
+    .try_into()
+    .unwrap();
+  let shared = key.diffie_hellman(&ephemeral_public);
+  if shared == [0; 32] {
+    return Err(Error::CorruptCiphertext(
+      "ephemeral key is a low-order point".to_string(),
+    ));
+  }
+
+  let key = recipient_key(&shared, &ephemeral_public, &recipient);
+  let aad = [associated_data, header].concat();
+  crypto::open(&key, &nonce, &aad, ciphertext).ok_or_else(|| {
+    Error::CorruptCiphertext("authentication failed, the message was altered".to_string())
+  })
+}
+
+/// The fingerprint of the key a payload was sealed for, if it was sealed
+/// with [`seal_for_recipient`].
+pub fn recipient_fingerprint(sealed: &[u8]) -> Option<Fingerprint> {
+  if sealed.len() < RECIPIENT_HEADER_SIZE || sealed[0] != SCHEME_RECIPIENT {
+    return None;
+  }
+  Some(Fingerprint::from_bytes(
+    sealed[1..1 + Fingerprint::SIZE].try_into().unwrap(),
+  ))
+}
+
+fn recipient_key(shared: &[u8; 32], ephemeral: &PublicKey, recipient: &PublicKey) -> [u8; 32] {
+  crypto::blake2b_256(&[
+    b"pngme-x25519-chacha20poly1305",
+    shared,
+    &ephemeral.bytes(),
+    &recipient.bytes(),
+  ])
+}
+
+/// Checks the scheme byte and minimum length. A payload sealed with the
+/// other scheme needs a different secret rather than being corrupt.
+fn check_scheme(sealed: &[u8], expected: u8, header_size: usize) -> Result<()> {
+  match sealed.first() {
+    Some(&scheme) if scheme == expected => {}
+    Some(&SCHEME_PASSWORD) => {
+      return Err(Error::WrongDecryptionMethod(
+        "message is encrypted with a password, not a key".to_string(),
+      ))
+    }
+    Some(&SCHEME_RECIPIENT) => {
+      return Err(Error::WrongDecryptionMethod(
+        "message is encrypted to a public key, not a password".to_string(),
+      ))
+    }
+    Some(&scheme) => {
+      return Err(Error::CorruptCiphertext(format!(
+        "unknown encryption scheme {}",
+        scheme
+      )))
+    }
+    None => {}
+  }
+  if sealed.len() < header_size + TAG_SIZE {
+    return Err(Error::CorruptCiphertext(format!(
+      "{} bytes is too short for an encrypted message",
+      sealed.len()
+    )));
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::crypto::tests::hex;
+
+  const FAST: KdfParams = KdfParams {
+    memory_kib: 64,
+    iterations: 1,
+    lanes: 1,
+  };
+
+  #[test]
+  fn test_password_round_trip() {
+    let sealed = seal_with_password(b"attack at dawn", "hunter2", b"RuSt", FAST).unwrap();
+    assert_eq!(sealed.len(), PASSWORD_HEADER_SIZE + 14 + 16);
+    assert_eq!(
+      open_with_password(&sealed, "hunter2", b"RuSt").unwrap(),
+      b"attack at dawn"
+    );
+  }
+
+  #[test]
+  fn test_fresh_salt_and_nonce() {
+    let a = seal_with_password(b"same", "pw", b"", FAST).unwrap();
+    let b = seal_with_password(b"same", "pw", b"", FAST).unwrap();
+    assert_ne!(a, b);
+  }
+
+  #[test]
+  fn test_wrong_password() {
+    let sealed = seal_with_password(b"secret", "right", b"", FAST).unwrap();
+    assert!(matches!(
+      open_with_password(&sealed, "wrong", b""),
+      Err(Error::WrongPassword)
+    ));
+  }
+
+  #[test]
+  fn test_corruption_is_not_a_wrong_password() {
+    let sealed = seal_with_password(b"secret", "pw", b"RuSt", FAST).unwrap();
+
+    let mut altered = sealed.clone();
+    *altered.last_mut().unwrap() ^= 1;
+    assert!(matches!(
+      open_with_password(&altered, "pw", b"RuSt"),
+      Err(Error::CorruptCiphertext(_))
+    ));
+    assert!(matches!(
+      open_with_password(&sealed, "pw", b"ruSt"),
+      Err(Error::CorruptCiphertext(_))
+    ));
+    assert!(matches!(
+      open_with_password(&sealed[..20], "pw", b"RuSt"),
+      Err(Error::CorruptCiphertext(_))
+    ));
+  }
+
+  #[test]
+  fn test_recipient_round_trip() {
+    let key = SecretKey::generate().unwrap();
+    let sealed = seal_for_recipient(b"for your eyes only", &key.public_key(), b"RuSt").unwrap();
+    assert_eq!(
+      recipient_fingerprint(&sealed),
+      Some(key.public_key().fingerprint())
+    );
+    assert_eq!(
+      open_with_key(&sealed, &key, b"RuSt").unwrap(),
+      b"for your eyes only"
+    );
+  }
+
+  #[test]
+  fn test_wrong_key() {
+    let key = SecretKey::generate().unwrap();
+    let other = SecretKey::generate().unwrap();
+    let sealed = seal_for_recipient(b"secret", &key.public_key(), b"").unwrap();
+    assert!(matches!(
+      open_with_key(&sealed, &other, b""),
+      Err(Error::WrongKey(fingerprint)) if fingerprint == key.public_key().fingerprint().to_string()
+    ));
+  }
+
+  #[test]
+  fn test_recipient_corruption() {
+    let key = SecretKey::generate().unwrap();
+    let sealed = seal_for_recipient(b"secret", &key.public_key(), b"").unwrap();
+    for index in [12, RECIPIENT_HEADER_SIZE - 1, sealed.len() - 1] {
+      let mut altered = sealed.clone();
+      altered[index] ^= 1;
+      assert!(matches!(
+        open_with_key(&altered, &key, b""),
+        Err(Error::CorruptCiphertext(_))
+      ));
+    }
+  }
+
+  #[test]
+  fn test_schemes_are_not_mixed_up() {
+    let key = SecretKey::generate().unwrap();
+    let for_key = seal_for_recipient(b"secret", &key.public_key(), b"").unwrap();
+    let for_password = seal_with_password(b"secret", "pw", b"", FAST).unwrap();
+    assert!(matches!(
+      open_with_password(&for_key, "pw", b""),
+      Err(Error::WrongDecryptionMethod(_))
+    ));
+    assert!(matches!(
+      open_with_key(&for_password, &key, b""),
+      Err(Error::WrongDecryptionMethod(_))
+    ));
+    assert_eq!(recipient_fingerprint(&for_password), None);
+  }
+
+  #[test]
+  fn test_key_derivation_is_stable() {
+    let (key, check) = FAST.derive(b"hunter2", &[7; 16]).unwrap();
+    assert_eq!(
+      key.to_vec(),
+      hex("d1c6b713667489eda7eba1dce426884df8fdd4258d749cfab0611da6a991d00a")
+    );
+    assert_eq!(check.to_vec(), hex("30a5960b135c3706e3712336a843d015"));
+  }
+
+  #[test]
+  fn test_opens_previously_sealed_messages() {
+    let sealed = hex(
+      "01000000400000000101f0fff2a60001c1f026b8b9b65e1214cb7931f16c896a\
+       31bfa2ad45fd1218bf443239a3909a9b16e9d603176013f619d3a479f8f1b9a9\
+       d8fc06badb094b2fd3538e200778278e117661",
+    );
+    assert_eq!(
+      open_with_password(&sealed, "hunter2", b"ruSt").unwrap(),
+      b"sealed before"
+    );
+
+    let sealed = hex(
+      "02197e3b4d21001014fc9d37f7494f506494bb3dd90552a0c1aed06ee199a544\
+       9c64c8aeafd0099b2c47996743736425574c83b96db001ef1754db7b5dbca35e\
+       485f5980a94aaff00e29a6c3bd9d3aa56358",
+    );
+    let key = SecretKey::from_bytes([2; 32]);
+    assert_eq!(
+      open_with_key(&sealed, &key, b"ruSt").unwrap(),
+      b"sealed before"
+    );
+  }
+
+  #[test]
+  fn test_rejects_excessive_parameters() {
+    let mut sealed = seal_with_password(b"secret", "pw", b"", FAST).unwrap();
+    sealed[1..5].copy_from_slice(&u32::MAX.to_be_bytes());
+    assert!(matches!(
+      open_with_password(&sealed, "pw", b""),
+      Err(Error::CorruptCiphertext(_))
+    ));
+  }
+}
@@ -0,0 +1,359 @@
+//! The versioned container `encode` wraps every message in.
+//!
+//! An envelope is laid out as:
+//!
+//! | bytes | field                                            |
+//! |-------|--------------------------------------------------|
+//! | 4     | magic, `89 50 4d 45` (`\x89PME`)                 |
+//! | 1     | version, currently `1`                           |
+//! | 1     | flags, see below                                 |
+//! | 1     | content type, see [`ContentType`]                |
+//! | 4     | payload length, big-endian                       |
+//! | n     | payload                                          |
+//! | 4     | CRC-32 of everything before it, if flagged       |
+//!
+//! Flag bits, from least significant: `0` the payload is compressed (see
+//! [`compression`]), `1` the payload is encrypted (see [`encryption`]),
+//! `2` a checksum follows the payload. A compressed payload is compressed
+//! before it is encrypted. Unknown flag bits are rejected, so a reader never
+//! silently misses a layer it does not understand; newer layouts bump the
+//! version instead.
+//!
+//! Data that does not start with the magic is a bare payload written before
+//! envelopes existed. The magic's first byte is not valid UTF-8 text and is
+//! none of the leading bytes a bare compressed or encrypted payload uses,
+//! so the two can always be told apart.
+//!
+//! [`compression`]: crate::compression
+//! [`encryption`]: crate::encryption
+
+use std::fmt::Display;
+
+use crate::{crc::Crc32, Error, Result};
+
+const FLAG_COMPRESSED: u8 = 1 << 0;
+const FLAG_ENCRYPTED: u8 = 1 << 1;
+const FLAG_CHECKSUM: u8 = 1 << 2;
+const KNOWN_FLAGS: u8 = FLAG_COMPRESSED | FLAG_ENCRYPTED | FLAG_CHECKSUM;
+const HEADER_SIZE: usize = 4 + 1 + 1 + 1 + 4;
+
+/// What the message is, once decrypted and decompressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+  /// UTF-8 text.
+  Text,
+  /// Arbitrary bytes.
+  Binary,
+  /// A file with its name and MIME type, see [`Attachment`].
+  ///
+  /// [`Attachment`]: crate::attachment::Attachment
+  File,
+  /// A type from a newer version of pngme.
+  Other(u8),
+}
+
+impl ContentType {
+  fn id(self) -> u8 {
+    match self {
+      ContentType::Text => 1,
+      ContentType::Binary => 2,
+      ContentType::File => 3,
+      ContentType::Other(id) => id,
+    }
+  }
+
+  fn from_id(id: u8) -> Self {
+    match id {
+      1 => ContentType::Text,
+      2 => ContentType::Binary,
+      3 => ContentType::File,
+      id => ContentType::Other(id),
+    }
+  }
+}
+
+impl Display for ContentType {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ContentType::Text => write!(f, "text"),
+      ContentType::Binary => write!(f, "binary"),
+      ContentType::File => write!(f, "file"),
+      ContentType::Other(id) => write!(f, "unknown content type {}", id),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+  flags: u8,
+  content_type: ContentType,
+  payload: Vec<u8>,
+}
+
+impl TryFrom<&[u8]> for Envelope {
+  type Error = Error;
+
+  fn try_from(data: &[u8]) -> Result<Self> {
+    if !Envelope::is_envelope(data) {
+      return Err(Error::InvalidEnvelope("missing magic".to_string()));
+    }
+    if data.len() < HEADER_SIZE {
+      return Err(Error::InvalidEnvelope("truncated header".to_string()));
+    }
+    if data[4] != Envelope::VERSION {
+      return Err(Error::UnsupportedEnvelopeVersion(data[4]));
+    }
+    let flags = data[5];
+    if flags & !KNOWN_FLAGS != 0 {
+      return Err(Error::InvalidEnvelope(format!(
+        "unknown flags {:#04x}",
+        flags & !KNOWN_FLAGS
+      )));
+    }
+    let length = u32::from_be_bytes(data[7..HEADER_SIZE].try_into().unwrap()) as usize;
+    let checksum_size = if flags & FLAG_CHECKSUM != 0 { 4 } else { 0 };
+    let expected = HEADER_SIZE as u64 + length as u64 + checksum_size as u64;
+    if data.len() as u64 != expected {
+      return Err(Error::InvalidEnvelope(format!(
+        "expected {} bytes, found {}",
+        expected,
+        data.len()
+      )));
+    }
+
+    let end = HEADER_SIZE + length;
+    if checksum_size > 0 {
+      let stored = u32::from_be_bytes(data[end..].try_into().unwrap());
+      let computed = checksum(&data[..end]);
+      if stored != computed {
+        return Err(Error::InvalidEnvelope(format!(
+          "checksum mismatch: expected {:08x}, found {:08x}",
+          stored, computed
+        )));
+      }
+    }
+
+    Ok(Envelope {
+      flags,
+      content_type: ContentType::from_id(data[6]),
+      payload: data[HEADER_SIZE..end].to_vec(),
+    })
+  }
+}
+
+/// A one-line summary, e.g. `envelope v1: text, compressed, encrypted,
+/// 52 byte payload, checksum`.
+impl Display for Envelope {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "envelope v{}: {}", Envelope::VERSION, self.content_type)?;
+    if self.is_compressed() {
+      write!(f, ", compressed")?;
+    }
+    if self.is_encrypted() {
+      write!(f, ", encrypted")?;
+    }
+    write!(f, ", {} byte payload", self.payload.len())?;
+    if self.has_checksum() {
+      write!(f, ", checksum")?;
+    }
+    Ok(())
+  }
+}
+
+impl Envelope {
+  pub const MAGIC: [u8; 4] = [0x89, b'P', b'M', b'E'];
+  pub const VERSION: u8 = 1;
+
+  /// An envelope with an empty payload and no flags set.
+  pub fn new(content_type: ContentType) -> Self {
+    Envelope {
+      flags: 0,
+      content_type,
+      payload: Vec::new(),
+    }
+  }
+
+  /// Whether `data` starts with the envelope magic rather than being a bare
+  /// payload.
+  pub fn is_envelope(data: &[u8]) -> bool {
+    data.starts_with(&Envelope::MAGIC)
+  }
+
+  /// Marks the payload as compressed.
+  pub fn compressed(self, compressed: bool) -> Self {
+    self.with_flag(FLAG_COMPRESSED, compressed)
+  }
+
+  /// Marks the payload as encrypted.
+  pub fn encrypted(self, encrypted: bool) -> Self {
+    self.with_flag(FLAG_ENCRYPTED, encrypted)
+  }
+
+  /// Appends a CRC-32 when serialized.
+  pub fn checksum(self, checksum: bool) -> Self {
+    self.with_flag(FLAG_CHECKSUM, checksum)
+  }
+
+  pub fn payload(mut self, payload: Vec<u8>) -> Self {
+    self.payload = payload;
+    self
+  }
+
+  fn with_flag(mut self, flag: u8, set: bool) -> Self {
+    if set {
+      self.flags |= flag;
+    } else {
+      self.flags &= !flag;
+    }
+    self
+  }
+
+  pub fn content_type(&self) -> ContentType {
+    self.content_type
+  }
+
+  pub fn is_compressed(&self) -> bool {
+    self.flags & FLAG_COMPRESSED != 0
+  }
+
+  pub fn is_encrypted(&self) -> bool {
+    self.flags & FLAG_ENCRYPTED != 0
+  }
+
+  pub fn has_checksum(&self) -> bool {
+    self.flags & FLAG_CHECKSUM != 0
+  }
+
+  pub fn payload_bytes(&self) -> &[u8] {
+    &self.payload
+  }
+
+  pub fn into_payload(self) -> Vec<u8> {
+    self.payload
+  }
+
+  /// The header fields an encrypted payload should be bound to, so that
+  /// flags and content type cannot be changed without breaking the tag.
+  pub fn associated_data(&self) -> [u8; 3] {
+    [Envelope::VERSION, self.flags, self.content_type.id()]
+  }
+
+  pub fn as_bytes(&self) -> Result<Vec<u8>> {
+    let length = u32::try_from(self.payload.len()).map_err(|_| Error::MessageTooLarge {
+      size: self.payload.len(),
+      capacity: u32::MAX as usize,
+    })?;
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + self.payload.len() + 4);
+    bytes.extend_from_slice(&Envelope::MAGIC);
+    bytes.extend_from_slice(&self.associated_data());
+    bytes.extend_from_slice(&length.to_be_bytes());
+    bytes.extend_from_slice(&self.payload);
+    if self.has_checksum() {
+      let crc = checksum(&bytes);
+      bytes.extend_from_slice(&crc.to_be_bytes());
+    }
+    Ok(bytes)
+  }
+}
+
+fn checksum(bytes: &[u8]) -> u32 {
+  let mut crc = Crc32::new();
+  crc.update(bytes);
+  crc.finish()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn sample() -> Envelope {
+    Envelope::new(ContentType::Text)
+      .compressed(true)
+      .checksum(true)
+      .payload(b"payload".to_vec())
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let envelope = sample();
+    let bytes = envelope.as_bytes().unwrap();
+    assert_eq!(bytes.len(), HEADER_SIZE + 7 + 4);
+    assert!(Envelope::is_envelope(&bytes));
+    assert_eq!(Envelope::try_from(bytes.as_slice()).unwrap(), envelope);
+
+    let plain = Envelope::new(ContentType::Binary).payload(vec![0, 1, 2]);
+    let bytes = plain.as_bytes().unwrap();
+    assert_eq!(bytes.len(), HEADER_SIZE + 3);
+    let parsed = Envelope::try_from(bytes.as_slice()).unwrap();
+    assert_eq!(parsed.content_type(), ContentType::Binary);
+    assert!(!parsed.is_compressed() && !parsed.is_encrypted() && !parsed.has_checksum());
+    assert_eq!(parsed.into_payload(), vec![0, 1, 2]);
+  }
+
+  #[test]
+  fn test_layout() {
+    let bytes = sample().as_bytes().unwrap();
+    assert_eq!(&bytes[..11], b"\x89PME\x01\x05\x01\x00\x00\x00\x07");
+    assert_eq!(&bytes[11..18], b"payload");
+  }
+
+  #[test]
+  fn test_bare_payloads_are_not_envelopes() {
+    assert!(!Envelope::is_envelope(b"hello"));
+    assert!(!Envelope::is_envelope(&[0, 1, 0, 0, 0, 5]));
+    assert!(!Envelope::is_envelope(&[1]));
+    assert!(!Envelope::is_envelope(&[2]));
+    assert!(matches!(
+      Envelope::try_from(&b"hello"[..]),
+      Err(Error::InvalidEnvelope(_))
+    ));
+  }
+
+  #[test]
+  fn test_rejects_malformed_envelopes() {
+    let bytes = sample().as_bytes().unwrap();
+
+    assert!(Envelope::try_from(&bytes[..8]).is_err());
+    assert!(Envelope::try_from(&bytes[..bytes.len() - 1]).is_err());
+
+    let mut version = bytes.clone();
+    version[4] = 2;
+    assert!(matches!(
+      Envelope::try_from(version.as_slice()),
+      Err(Error::UnsupportedEnvelopeVersion(2))
+    ));
+
+    let mut flags = bytes.clone();
+    flags[5] |= 0x80;
+    assert!(matches!(
+      Envelope::try_from(flags.as_slice()),
+      Err(Error::InvalidEnvelope(_))
+    ));
+
+    let mut corrupt = bytes;
+    corrupt[12] ^= 1;
+    assert!(matches!(
+      Envelope::try_from(corrupt.as_slice()),
+      Err(Error::InvalidEnvelope(_))
+    ));
+  }
+
+  #[test]
+  fn test_unknown_content_type_is_kept() {
+    let mut bytes = sample().as_bytes().unwrap();
+    bytes[6] = 42;
+    bytes.truncate(bytes.len() - 4);
+    bytes[5] &= !FLAG_CHECKSUM;
+    let envelope = Envelope::try_from(bytes.as_slice()).unwrap();
+    assert_eq!(envelope.content_type(), ContentType::Other(42));
+    assert_eq!(envelope.as_bytes().unwrap(), bytes);
+  }
+
+  #[test]
+  fn test_display() {
+    assert_eq!(
+      sample().encrypted(true).to_string(),
+      "envelope v1: text, compressed, encrypted, 7 byte payload, checksum"
+    );
+  }
+}
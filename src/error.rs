@@ -0,0 +1,284 @@
+use std::{fmt::Display, io, string::FromUtf8Error};
+
+/// Everything that can go wrong while reading, editing or writing a PNG.
+///
+/// Offsets are byte positions in the input being parsed; for a [`Png`] they
+/// count from the start of the file, signature included.
+///
+/// [`Png`]: crate::png::Png
+#[derive(Debug)]
+pub enum Error {
+  /// A chunk type byte at `index` is not an ASCII letter.
+  InvalidChunkTypeByte {
+    byte: u8,
+    index: usize,
+  },
+  /// A chunk type string is not exactly four bytes long.
+  InvalidChunkTypeLength(usize),
+  /// The chunk type is well-formed but has the reserved bit set.
+  ReservedChunkType(String),
+  /// Chunk data is longer than the 2^31 - 1 bytes the PNG spec allows.
+  ChunkTooLarge(usize),
+  /// A chunk's declared length disagrees with the bytes it was given.
+  LengthMismatch {
+    offset: usize,
+    declared: u32,
+    actual: usize,
+  },
+  /// The stored CRC does not match the one computed over type and data.
+  CrcMismatch {
+    offset: usize,
+    expected: u32,
+    found: u32,
+  },
+  /// The input does not start with the 8-byte PNG signature.
+  InvalidSignature,
+  /// The input ended before a complete chunk could be read.
+  Truncated {
+    offset: usize,
+    needed: usize,
+    available: usize,
+  },
+  /// Chunk data was requested from a [`ChunkReader`] before reading the
+  /// chunk's header with `next_header`, or after the data was consumed.
+  ///
+  /// [`ChunkReader`]: crate::reader::ChunkReader
+  NoCurrentChunk,
+  /// The IHDR chunk is missing, malformed or violates the PNG spec.
+  InvalidImageHeader(String),
+  /// A scanline starts with an unknown filter type.
+  InvalidFilterType(u8),
+  /// Decoded image data does not match the dimensions in IHDR.
+  InvalidImageData(String),
+  /// A zlib or DEFLATE stream is malformed.
+  InvalidCompressedData(String),
+  /// The Adler-32 checksum of decompressed data does not match the stream.
+  AdlerMismatch {
+    expected: u32,
+    found: u32,
+  },
+  /// Decompression would produce more than the given number of bytes.
+  OutputLimitExceeded(usize),
+  /// The message does not fit in the space the image offers.
+  MessageTooLarge {
+    size: usize,
+    capacity: usize,
+  },
+  /// The pixels do not carry a message embedded with the given options.
+  NoHiddenMessage,
+  /// The password does not match the one the message was sealed with.
+  WrongPassword,
+  /// An encrypted message is malformed or was altered after sealing.
+  CorruptCiphertext(String),
+  /// A message was encrypted for the key with the given fingerprint.
+  WrongKey(String),
+  /// The message has to be opened another way, e.g. with a key rather than
+  /// a password.
+  WrongDecryptionMethod(String),
+  /// A key file or key is malformed or unusable.
+  InvalidKey(String),
+  /// A message envelope is malformed.
+  InvalidEnvelope(String),
+  /// A message envelope has a version this build does not understand.
+  UnsupportedEnvelopeVersion(u8),
+  /// An embedded file is malformed or has an unusable name.
+  InvalidAttachment(String),
+  /// The message is an embedded file, which is only written to a path.
+  OutputPathRequired {
+    name: String,
+    mime_type: String,
+  },
+  /// A fragment chunk is malformed, or fragments do not fit together.
+  InvalidFragment(String),
+  /// Some fragments of a message are missing.
+  MissingFragments {
+    count: u16,
+    missing: Vec<u16>,
+  },
+  /// The fragment with this index appears more than once.
+  DuplicateFragment(u16),
+  /// The manifest chunk naming stored messages is malformed.
+  InvalidManifest(String),
+  /// A message with this name is already stored.
+  MessageNameTaken(String),
+  /// No message with this name is stored.
+  MessageNotFound(String),
+  /// A signature chunk is malformed.
+  InvalidSignatureChunk(String),
+  /// No signature accompanies the chunk of the given type.
+  SignatureNotFound(String),
+  /// The signature by the key with the given fingerprint does not match
+  /// the message.
+  BadSignature(String),
+  /// The message is signed by the key with the given fingerprint rather
+  /// than the expected one.
+  WrongSigner(String),
+  /// No chunk of the requested type exists.
+  ChunkNotFound(String),
+  /// Chunk data is not valid UTF-8.
+  InvalidUtf8(FromUtf8Error),
+  /// The command line could not be parsed.
+  Usage(String),
+  Io(io::Error),
+}
+
+impl Error {
+  /// Shifts any offset in the error by `base`, for errors raised while
+  /// parsing a sub-slice of a larger input.
+  pub(crate) fn offset_by(mut self, base: usize) -> Self {
+    match &mut self {
+      Error::LengthMismatch { offset, .. }
+      | Error::CrcMismatch { offset, .. }
+      | Error::Truncated { offset, .. } => *offset += base,
+      _ => {}
+    }
+    self
+  }
+}
+
+impl Display for Error {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Error::InvalidChunkTypeByte { byte, index } => write!(
+        f,
+        "invalid chunk type byte 0x{:02x} at position {}: only ascii letters are allowed",
+        byte, index
+      ),
+      Error::InvalidChunkTypeLength(length) => {
+        write!(f, "chunk type must be 4 bytes long, got {}", length)
+      }
+      Error::ReservedChunkType(chunk_type) => {
+        write!(f, "chunk type '{}' has the reserved bit set", chunk_type)
+      }
+      Error::ChunkTooLarge(length) => write!(
+        f,
+        "chunk data of {} bytes is over the limit of {} bytes",
+        length,
+        crate::Chunk::MAX_LENGTH
+      ),
+      Error::LengthMismatch {
+        offset,
+        declared,
+        actual,
+      } => write!(
+        f,
+        "chunk at offset {} declares {} data bytes but has {}",
+        offset, declared, actual
+      ),
+      Error::CrcMismatch {
+        offset,
+        expected,
+        found,
+      } => write!(
+        f,
+        "crc mismatch in chunk at offset {}: expected {:08x}, found {:08x}",
+        offset, expected, found
+      ),
+      Error::InvalidSignature => write!(f, "invalid png signature"),
+      Error::Truncated {
+        offset,
+        needed,
+        available,
+      } => write!(
+        f,
+        "truncated input at offset {}: needed {} bytes, {} available",
+        offset, needed, available
+      ),
+      Error::NoCurrentChunk => write!(f, "no chunk header has been read"),
+      Error::InvalidImageHeader(reason) => write!(f, "invalid image header: {}", reason),
+      Error::InvalidFilterType(filter) => write!(f, "invalid scanline filter type {}", filter),
+      Error::InvalidImageData(reason) => write!(f, "invalid image data: {}", reason),
+      Error::InvalidCompressedData(reason) => write!(f, "invalid compressed data: {}", reason),
+      Error::AdlerMismatch { expected, found } => write!(
+        f,
+        "adler-32 mismatch: expected {:08x}, found {:08x}",
+        expected, found
+      ),
+      Error::OutputLimitExceeded(limit) => {
+        write!(f, "decompressed data exceeds the limit of {} bytes", limit)
+      }
+      Error::MessageTooLarge { size, capacity } => write!(
+        f,
+        "message of {} bytes does not fit, the image holds at most {}",
+        size, capacity
+      ),
+      Error::NoHiddenMessage => write!(f, "no hidden message found in the pixel data"),
+      Error::WrongPassword => write!(f, "wrong password"),
+      Error::CorruptCiphertext(reason) => write!(f, "corrupt encrypted message: {}", reason),
+      Error::WrongDecryptionMethod(reason) => write!(f, "{}", reason),
+      Error::WrongKey(fingerprint) => write!(
+        f,
+        "message is encrypted for key {}, not this one",
+        fingerprint
+      ),
+      Error::InvalidKey(reason) => write!(f, "invalid key: {}", reason),
+      Error::InvalidEnvelope(reason) => write!(f, "invalid message envelope: {}", reason),
+      Error::UnsupportedEnvelopeVersion(version) => write!(
+        f,
+        "message envelope version {} is not supported, upgrade pngme",
+        version
+      ),
+      Error::InvalidAttachment(reason) => write!(f, "invalid embedded file: {}", reason),
+      Error::OutputPathRequired { name, mime_type } => write!(
+        f,
+        "the message is the file '{}' ({}), give --output <path>",
+        name, mime_type
+      ),
+      Error::InvalidFragment(reason) => write!(f, "invalid message fragment: {}", reason),
+      Error::MissingFragments { count, missing } => {
+        let missing: Vec<String> = missing.iter().map(u16::to_string).collect();
+        write!(
+          f,
+          "missing {} of {} message fragments: {}",
+          missing.len(),
+          count,
+          missing.join(", ")
+        )
+      }
+      Error::DuplicateFragment(index) => {
+        write!(f, "message fragment {} appears more than once", index)
+      }
+      Error::InvalidManifest(reason) => write!(f, "invalid message manifest: {}", reason),
+      Error::MessageNameTaken(name) => write!(f, "a message named '{}' already exists", name),
+      Error::MessageNotFound(name) => write!(f, "no message named '{}'", name),
+      Error::InvalidSignatureChunk(reason) => write!(f, "invalid signature chunk: {}", reason),
+      Error::SignatureNotFound(chunk_type) => {
+        write!(f, "no signature found for the '{}' chunk", chunk_type)
+      }
+      Error::BadSignature(fingerprint) => {
+        write!(f, "signature by {} does not match the message", fingerprint)
+      }
+      Error::WrongSigner(fingerprint) => write!(
+        f,
+        "message is signed by {}, not the expected key",
+        fingerprint
+      ),
+      Error::ChunkNotFound(chunk_type) => write!(f, "no '{}' chunk found", chunk_type),
+      Error::InvalidUtf8(e) => write!(f, "chunk data is not valid utf-8: {}", e),
+      Error::Usage(message) => write!(f, "{}", message),
+      Error::Io(e) => write!(f, "{}", e),
+    }
+  }
+}
+
+impl std::error::Error for Error {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Error::InvalidUtf8(e) => Some(e),
+      Error::Io(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+impl From<io::Error> for Error {
+  fn from(value: io::Error) -> Self {
+    Error::Io(value)
+  }
+}
+
+impl From<FromUtf8Error> for Error {
+  fn from(value: FromUtf8Error) -> Self {
+    Error::InvalidUtf8(value)
+  }
+}
@@ -0,0 +1,337 @@
+//! PNG scanline filtering (PNG spec section 9).
+//!
+//! Filtered image data is a sequence of rows, each a filter type byte
+//! followed by `row_bytes` bytes. Filters work on bytes, comparing each byte
+//! with the corresponding byte of the previous pixel (`bytes_per_pixel`
+//! back, at least one) and of the row above.
+
+use crate::{
+  adam7,
+  deflate::{self, CompressionLevel},
+  image_header::{ImageHeader, InterlaceMethod},
+  Error, Result,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterType {
+  None,
+  Sub,
+  Up,
+  Average,
+  Paeth,
+}
+
+impl TryFrom<u8> for FilterType {
+  type Error = Error;
+  fn try_from(value: u8) -> Result<Self> {
+    match value {
+      0 => Ok(FilterType::None),
+      1 => Ok(FilterType::Sub),
+      2 => Ok(FilterType::Up),
+      3 => Ok(FilterType::Average),
+      4 => Ok(FilterType::Paeth),
+      other => Err(Error::InvalidFilterType(other)),
+    }
+  }
+}
+
+impl FilterType {
+  pub const ALL: [FilterType; 5] = [
+    FilterType::None,
+    FilterType::Sub,
+    FilterType::Up,
+    FilterType::Average,
+    FilterType::Paeth,
+  ];
+
+  pub fn value(&self) -> u8 {
+    match self {
+      FilterType::None => 0,
+      FilterType::Sub => 1,
+      FilterType::Up => 2,
+      FilterType::Average => 3,
+      FilterType::Paeth => 4,
+    }
+  }
+}
+
+/// How [`filter`] picks a filter for each row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterStrategy {
+  /// The same filter for every row.
+  Fixed(FilterType),
+  /// The filter whose output has the smallest sum of absolute values, taking
+  /// bytes as signed. This is the heuristic the PNG spec recommends.
+  #[default]
+  MinSum,
+  /// The filter whose output compresses smallest on its own. Slow.
+  BruteForce,
+}
+
+/// Turns decompressed image data into raw rows packed as described by
+/// `header`, deinterlacing if needed.
+pub fn unfilter_image(filtered: &[u8], header: &ImageHeader) -> Result<Vec<u8>> {
+  match header.interlace_method {
+    InterlaceMethod::None => unfilter(
+      filtered,
+      header.row_bytes(header.width),
+      header.height as usize,
+      header.bits_per_pixel() / 8,
+    ),
+    InterlaceMethod::Adam7 => adam7::deinterlace(filtered, header),
+  }
+}
+
+/// Turns raw rows packed as described by `header` into image data ready for
+/// compression, interlacing if needed.
+pub fn filter_image(raw: &[u8], header: &ImageHeader, strategy: FilterStrategy) -> Result<Vec<u8>> {
+  let row_bytes = header.row_bytes(header.width);
+  let rows = header.height as usize;
+  let expected = row_bytes
+    .checked_mul(rows)
+    .ok_or_else(|| Error::InvalidImageData("image is too large".to_string()))?;
+  if raw.len() != expected {
+    return Err(Error::InvalidImageData(format!(
+      "expected {} bytes of pixel data, got {}",
+      expected,
+      raw.len()
+    )));
+  }
+
+  Ok(match header.interlace_method {
+    InterlaceMethod::None => filter(raw, row_bytes, rows, header.bits_per_pixel() / 8, strategy),
+    InterlaceMethod::Adam7 => adam7::interlace(raw, header, strategy),
+  })
+}
+
+/// Reverses the filters on `rows` rows of `row_bytes` bytes each, returning
+/// the raw rows without filter type bytes.
+pub fn unfilter(
+  data: &[u8],
+  row_bytes: usize,
+  rows: usize,
+  bytes_per_pixel: usize,
+) -> Result<Vec<u8>> {
+  let too_large = || Error::InvalidImageData("image is too large".to_string());
+  let expected = row_bytes
+    .checked_add(1)
+    .and_then(|filtered_row| filtered_row.checked_mul(rows))
+    .ok_or_else(too_large)?;
+  let size = row_bytes.checked_mul(rows).ok_or_else(too_large)?;
+  if data.len() != expected {
+    return Err(Error::InvalidImageData(format!(
+      "expected {} bytes of filtered data, got {}",
+      expected,
+      data.len()
+    )));
+  }
+
+  let bpp = bytes_per_pixel.max(1);
+  let mut output = vec![0u8; size];
+  let mut previous = vec![0u8; row_bytes];
+  for (filtered, row) in data
+    .chunks_exact(row_bytes + 1)
+    .zip(output.chunks_exact_mut(row_bytes.max(1)))
+  {
+    let filter = FilterType::try_from(filtered[0])?;
+    let filtered = &filtered[1..];
+    for i in 0..row_bytes {
+      let left = if i >= bpp { row[i - bpp] } else { 0 };
+      let up = previous[i];
+      let up_left = if i >= bpp { previous[i - bpp] } else { 0 };
+      row[i] = filtered[i].wrapping_add(predict(filter, left, up, up_left));
+    }
+    previous.copy_from_slice(&row[..row_bytes]);
+  }
+  Ok(output)
+}
+
+/// Filters `rows` raw rows of `row_bytes` bytes each, prefixing each row with
+/// the filter type `strategy` chose for it.
+pub fn filter(
+  raw: &[u8],
+  row_bytes: usize,
+  rows: usize,
+  bytes_per_pixel: usize,
+  strategy: FilterStrategy,
+) -> Vec<u8> {
+  assert_eq!(
+    raw.len(),
+    row_bytes * rows,
+    "raw data does not match dimensions"
+  );
+
+  let bpp = bytes_per_pixel.max(1);
+  let mut output = Vec::with_capacity((row_bytes + 1) * rows);
+  let mut candidate = vec![0u8; row_bytes];
+  let empty = vec![0u8; row_bytes];
+  for (index, row) in raw.chunks_exact(row_bytes.max(1)).take(rows).enumerate() {
+    let previous = if index == 0 {
+      &empty[..]
+    } else {
+      &raw[(index - 1) * row_bytes..index * row_bytes]
+    };
+
+    let chosen = match strategy {
+      FilterStrategy::Fixed(filter) => filter,
+      FilterStrategy::MinSum => best_filter(|filter| {
+        apply(filter, row, previous, bpp, &mut candidate);
+        candidate
+          .iter()
+          .map(|&byte| (byte as i8).unsigned_abs() as usize)
+          .sum()
+      }),
+      FilterStrategy::BruteForce => best_filter(|filter| {
+        apply(filter, row, previous, bpp, &mut candidate);
+        deflate::deflate(&candidate, CompressionLevel::Fast).len()
+      }),
+    };
+
+    apply(chosen, row, previous, bpp, &mut candidate);
+    output.push(chosen.value());
+    output.extend_from_slice(&candidate);
+  }
+  output
+}
+
+fn best_filter(mut cost: impl FnMut(FilterType) -> usize) -> FilterType {
+  FilterType::ALL
+    .into_iter()
+    .min_by_key(|&filter| cost(filter))
+    .unwrap()
+}
+
+fn apply(filter: FilterType, row: &[u8], previous: &[u8], bpp: usize, output: &mut [u8]) {
+  for i in 0..output.len() {
+    let left = if i >= bpp { row[i - bpp] } else { 0 };
+    let up_left = if i >= bpp { previous[i - bpp] } else { 0 };
+    output[i] = row[i].wrapping_sub(predict(filter, left, previous[i], up_left));
+  }
+}
+
+fn predict(filter: FilterType, left: u8, up: u8, up_left: u8) -> u8 {
+  match filter {
+    FilterType::None => 0,
+    FilterType::Sub => left,
+    FilterType::Up => up,
+    FilterType::Average => ((left as u16 + up as u16) / 2) as u8,
+    FilterType::Paeth => paeth(left, up, up_left),
+  }
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+  let p = a as i16 + b as i16 - c as i16;
+  let pa = (p - a as i16).abs();
+  let pb = (p - b as i16).abs();
+  let pc = (p - c as i16).abs();
+  if pa <= pb && pa <= pc {
+    a
+  } else if pb <= pc {
+    b
+  } else {
+    c
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn gradient(row_bytes: usize, rows: usize) -> Vec<u8> {
+    (0..rows)
+      .flat_map(|y| (0..row_bytes).map(move |x| (x * 7 + y * 13 + (x * y) % 5) as u8))
+      .collect()
+  }
+
+  #[test]
+  fn test_unfilter_each_type() {
+    // Two rows of two 1-byte pixels.
+    let data = [
+      1, 10, 5, // Sub: 10, 15
+      4, 1, 2, // Paeth: 10 + 1 = 11, then paeth(11, 15, 10) = 15 + 2 = 17
+    ];
+    assert_eq!(unfilter(&data, 2, 2, 1).unwrap(), [10, 15, 11, 17]);
+
+    let data = [0, 10, 20, 2, 1, 1, 3, 1, 1];
+    assert_eq!(
+      unfilter(&data, 2, 3, 1).unwrap(),
+      [10, 20, 11, 21, 6, 14] // Up, then Average: (0 + 11) / 2 + 1, (6 + 21) / 2 + 1
+    );
+  }
+
+  #[test]
+  fn test_round_trip_all_strategies() {
+    let strategies = FilterType::ALL
+      .into_iter()
+      .map(FilterStrategy::Fixed)
+      .chain([FilterStrategy::MinSum, FilterStrategy::BruteForce]);
+    for strategy in strategies {
+      for bpp in [1, 3, 4, 8] {
+        let raw = gradient(bpp * 9, 7);
+        let filtered = filter(&raw, bpp * 9, 7, bpp, strategy);
+        assert_eq!(filtered.len(), (bpp * 9 + 1) * 7);
+        assert_eq!(
+          unfilter(&filtered, bpp * 9, 7, bpp).unwrap(),
+          raw,
+          "{:?}",
+          strategy
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn test_fixed_strategy_uses_one_filter() {
+    let raw = gradient(6, 4);
+    let filtered = filter(&raw, 6, 4, 3, FilterStrategy::Fixed(FilterType::Paeth));
+    assert!(filtered.chunks(7).all(|row| row[0] == 4));
+  }
+
+  #[test]
+  fn test_min_sum_prefers_smooth_filter() {
+    let raw: Vec<u8> = (0..4).flat_map(|_| 0..32u8).collect();
+    let filtered = filter(&raw, 32, 4, 1, FilterStrategy::MinSum);
+    assert_eq!(filtered[0], FilterType::Sub.value());
+    assert_eq!(filtered[33], FilterType::Up.value());
+  }
+
+  #[test]
+  fn test_invalid_input() {
+    assert!(matches!(
+      unfilter(&[5, 0, 0], 2, 1, 1),
+      Err(Error::InvalidFilterType(5))
+    ));
+    assert!(matches!(
+      unfilter(&[0, 0], 2, 1, 1),
+      Err(Error::InvalidImageData(_))
+    ));
+  }
+
+  #[test]
+  fn test_rejects_images_too_large_for_memory() {
+    for interlace_method in [InterlaceMethod::None, InterlaceMethod::Adam7] {
+      let header = ImageHeader {
+        width: i32::MAX as u32,
+        height: i32::MAX as u32,
+        bit_depth: 16,
+        color_type: crate::ColorType::Rgba,
+        compression_method: 0,
+        filter_method: 0,
+        interlace_method,
+      };
+      header.validate().unwrap();
+      assert!(matches!(
+        filter_image(&[], &header, FilterStrategy::MinSum),
+        Err(Error::InvalidImageData(_))
+      ));
+      assert!(matches!(
+        unfilter_image(&[], &header),
+        Err(Error::InvalidImageData(_))
+      ));
+    }
+    assert!(matches!(
+      unfilter(&[], usize::MAX, 2, 1),
+      Err(Error::InvalidImageData(_))
+    ));
+  }
+}
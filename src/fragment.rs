@@ -0,0 +1,264 @@
+//! Splitting a message payload across several chunks of the same type.
+//!
+//! Each fragment chunk holds:
+//!
+//! | bytes | field                                          |
+//! |-------|------------------------------------------------|
+//! | 4     | magic, `89 50 4d 46` (`\x89PMF`)               |
+//! | 2     | fragment index, from 0, big-endian             |
+//! | 2     | fragment count, big-endian                     |
+//! | 32    | BLAKE2b-256 of the whole payload               |
+//! | rest  | this fragment's slice of the payload           |
+//!
+//! The hash doubles as the identity of a fragmented message: fragments with
+//! the same hash belong together, so two fragmented messages can share a
+//! chunk type. Like the envelope magic, the fragment magic starts with a
+//! byte no bare payload starts with.
+
+use crate::{crypto, Error, Result};
+
+pub const MAGIC: [u8; 4] = [0x89, b'P', b'M', b'F'];
+pub const HASH_SIZE: usize = 32;
+pub const HEADER_SIZE: usize = 4 + 2 + 2 + HASH_SIZE;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+  index: u16,
+  count: u16,
+  hash: [u8; HASH_SIZE],
+  data: Vec<u8>,
+}
+
+impl TryFrom<&[u8]> for Fragment {
+  type Error = Error;
+
+  fn try_from(bytes: &[u8]) -> Result<Self> {
+    if !Fragment::is_fragment(bytes) {
+      return Err(Error::InvalidFragment("missing magic".to_string()));
+    }
+    if bytes.len() < HEADER_SIZE {
+      return Err(Error::InvalidFragment("truncated header".to_string()));
+    }
+    let index = u16::from_be_bytes([bytes[4], bytes[5]]);
+    let count = u16::from_be_bytes([bytes[6], bytes[7]]);
+    if index >= count {
+      return Err(Error::InvalidFragment(format!(
+        "fragment index {} is not below the count {}",
+        index, count
+      )));
+    }
+    Ok(Fragment {
+      index,
+      count,
+      hash: bytes[8..HEADER_SIZE].try_into().unwrap(),
+      data: bytes[HEADER_SIZE..].to_vec(),
+    })
+  }
+}
+
+impl Fragment {
+  /// Whether chunk data is a fragment rather than a whole payload.
+  pub fn is_fragment(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+  }
+
+  pub fn index(&self) -> u16 {
+    self.index
+  }
+
+  pub fn count(&self) -> u16 {
+    self.count
+  }
+
+  /// The hash of the whole payload, shared by all its fragments.
+  pub fn hash(&self) -> [u8; HASH_SIZE] {
+    self.hash
+  }
+
+  pub fn data(&self) -> &[u8] {
+    &self.data
+  }
+
+  pub fn as_bytes(&self) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + self.data.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&self.index.to_be_bytes());
+    bytes.extend_from_slice(&self.count.to_be_bytes());
+    bytes.extend_from_slice(&self.hash);
+    bytes.extend_from_slice(&self.data);
+    bytes
+  }
+}
+
+/// Splits `payload` into fragments carrying at most `max_data_size` bytes
+/// each. An empty payload still yields one fragment.
+pub fn split(payload: &[u8], max_data_size: usize) -> Result<Vec<Fragment>> {
+  if max_data_size == 0 {
+    return Err(Error::InvalidFragment(
+      "fragment size must be at least 1 byte".to_string(),
+    ));
+  }
+  let count = payload.len().div_ceil(max_data_size).max(1);
+  let count = u16::try_from(count).map_err(|_| {
+    Error::InvalidFragment(format!(
+      "{} fragments needed, at most {} are allowed",
+      count,
+      u16::MAX
+    ))
+  })?;
+  let hash = payload_hash(payload);
+
+  let mut pieces: Vec<&[u8]> = payload.chunks(max_data_size).collect();
+  if pieces.is_empty() {
+    pieces.push(&[]);
+  }
+  Ok(
+    pieces
+      .into_iter()
+      .enumerate()
+      .map(|(index, data)| Fragment {
+        index: index as u16,
+        count,
+        hash,
+        data: data.to_vec(),
+      })
+      .collect(),
+  )
+}
+
+/// Puts the fragments of one payload back together in index order. Every
+/// fragment must agree on the count and hash, each index must appear exactly
+/// once, and the result must match the hash.
+pub fn reassemble(fragments: Vec<Fragment>) -> Result<Vec<u8>> {
+  let first = fragments
+    .first()
+    .ok_or_else(|| Error::InvalidFragment("no fragments".to_string()))?;
+  let (count, hash) = (first.count, first.hash);
+
+  let mut slots: Vec<Option<Fragment>> = vec![None; count as usize];
+  for fragment in fragments {
+    if fragment.count != count || fragment.hash != hash {
+      return Err(Error::InvalidFragment(format!(
+        "fragment {} belongs to a different message",
+        fragment.index
+      )));
+    }
+    let index = fragment.index;
+    if slots[index as usize].replace(fragment).is_some() {
+      return Err(Error::DuplicateFragment(index));
+    }
+  }
+
+  let missing: Vec<u16> = (0..count)
+    .filter(|&index| slots[index as usize].is_none())
+    .collect();
+  if !missing.is_empty() {
+    return Err(Error::MissingFragments { count, missing });
+  }
+
+  let payload: Vec<u8> = slots
+    .into_iter()
+    .flat_map(|slot| slot.unwrap().data)
+    .collect();
+  if payload_hash(&payload) != hash {
+    return Err(Error::InvalidFragment(
+      "reassembled payload does not match its hash".to_string(),
+    ));
+  }
+  Ok(payload)
+}
+
+/// The hash every fragment of `payload` carries, which also identifies a
+/// whole payload in the [`manifest`](crate::manifest).
+pub fn payload_hash(payload: &[u8]) -> [u8; HASH_SIZE] {
+  crypto::blake2b_256(&[payload])
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn payload() -> Vec<u8> {
+    (0..1000u32).map(|i| (i * 7) as u8).collect()
+  }
+
+  #[test]
+  fn test_split_and_reassemble() {
+    let fragments = split(&payload(), 300).unwrap();
+    assert_eq!(fragments.len(), 4);
+    assert_eq!(fragments[3].data().len(), 100);
+    assert!(fragments.iter().all(|f| f.count() == 4));
+
+    let mut shuffled: Vec<Fragment> = fragments
+      .iter()
+      .map(|f| Fragment::try_from(f.as_bytes().as_slice()).unwrap())
+      .collect();
+    shuffled.reverse();
+    assert_eq!(reassemble(shuffled).unwrap(), payload());
+  }
+
+  #[test]
+  fn test_payload_hash_is_blake2b_256() {
+    assert_eq!(
+      payload_hash(b"pngme").to_vec(),
+      crate::crypto::tests::hex("c2afed7cd9c4215620419399d56a3c51e5514266abb5ec6e5d0b1cc734a47f45")
+    );
+  }
+
+  #[test]
+  fn test_empty_payload() {
+    let fragments = split(&[], 10).unwrap();
+    assert_eq!(fragments.len(), 1);
+    assert_eq!(reassemble(fragments).unwrap(), Vec::<u8>::new());
+  }
+
+  #[test]
+  fn test_missing_fragments() {
+    let mut fragments = split(&payload(), 100).unwrap();
+    fragments.remove(7);
+    fragments.remove(2);
+    assert!(matches!(
+      reassemble(fragments),
+      Err(Error::MissingFragments { count: 10, missing }) if missing == vec![2, 7]
+    ));
+  }
+
+  #[test]
+  fn test_duplicate_fragment() {
+    let mut fragments = split(&payload(), 100).unwrap();
+    fragments.push(fragments[4].clone());
+    assert!(matches!(
+      reassemble(fragments),
+      Err(Error::DuplicateFragment(4))
+    ));
+  }
+
+  #[test]
+  fn test_tampered_or_mixed_fragments() {
+    let mut fragments = split(&payload(), 100).unwrap();
+    fragments[3].data[0] ^= 1;
+    assert!(matches!(
+      reassemble(fragments),
+      Err(Error::InvalidFragment(_))
+    ));
+
+    let mut fragments = split(&payload(), 100).unwrap();
+    fragments[0] = split(b"other", 100).unwrap().remove(0);
+    assert!(matches!(
+      reassemble(fragments),
+      Err(Error::InvalidFragment(_))
+    ));
+  }
+
+  #[test]
+  fn test_rejects_malformed_headers() {
+    let bytes = split(b"hello", 2).unwrap()[1].as_bytes();
+    assert!(Fragment::try_from(&bytes[..HEADER_SIZE - 1]).is_err());
+    let mut bad_index = bytes.clone();
+    bad_index[5] = 3;
+    assert!(Fragment::try_from(bad_index.as_slice()).is_err());
+    assert!(!Fragment::is_fragment(b"hello"));
+    assert!(split(b"hello", 0).is_err());
+    assert!(split(&vec![0; 70_000], 1).is_err());
+  }
+}
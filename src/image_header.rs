@@ -0,0 +1,321 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{adam7, chunk::Chunk, chunk_type::ChunkType, Error, Result};
+
+/// Length of the IHDR chunk data.
+const IHDR_LENGTH: usize = 13;
+
+/// Largest width or height the PNG spec allows (2^31 - 1).
+const MAX_DIMENSION: u32 = i32::MAX as u32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+  Grayscale,
+  Rgb,
+  Indexed,
+  GrayscaleAlpha,
+  Rgba,
+}
+
+impl TryFrom<u8> for ColorType {
+  type Error = Error;
+  fn try_from(value: u8) -> Result<Self> {
+    match value {
+      0 => Ok(ColorType::Grayscale),
+      2 => Ok(ColorType::Rgb),
+      3 => Ok(ColorType::Indexed),
+      4 => Ok(ColorType::GrayscaleAlpha),
+      6 => Ok(ColorType::Rgba),
+      other => Err(Error::InvalidImageHeader(format!(
+        "unknown color type {}",
+        other
+      ))),
+    }
+  }
+}
+
+impl Display for ColorType {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let name = match self {
+      ColorType::Grayscale => "grayscale",
+      ColorType::Rgb => "RGB",
+      ColorType::Indexed => "indexed",
+      ColorType::GrayscaleAlpha => "grayscale+alpha",
+      ColorType::Rgba => "RGBA",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+impl ColorType {
+  /// The value stored in IHDR.
+  pub fn value(&self) -> u8 {
+    match self {
+      ColorType::Grayscale => 0,
+      ColorType::Rgb => 2,
+      ColorType::Indexed => 3,
+      ColorType::GrayscaleAlpha => 4,
+      ColorType::Rgba => 6,
+    }
+  }
+
+  /// Number of samples per pixel.
+  pub fn channels(&self) -> usize {
+    match self {
+      ColorType::Grayscale | ColorType::Indexed => 1,
+      ColorType::GrayscaleAlpha => 2,
+      ColorType::Rgb => 3,
+      ColorType::Rgba => 4,
+    }
+  }
+
+  /// Bit depths the PNG spec allows for this color type.
+  pub fn allowed_bit_depths(&self) -> &'static [u8] {
+    match self {
+      ColorType::Grayscale => &[1, 2, 4, 8, 16],
+      ColorType::Indexed => &[1, 2, 4, 8],
+      ColorType::Rgb | ColorType::GrayscaleAlpha | ColorType::Rgba => &[8, 16],
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterlaceMethod {
+  None,
+  Adam7,
+}
+
+/// The image properties stored in the IHDR chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageHeader {
+  pub width: u32,
+  pub height: u32,
+  pub bit_depth: u8,
+  pub color_type: ColorType,
+  pub compression_method: u8,
+  pub filter_method: u8,
+  pub interlace_method: InterlaceMethod,
+}
+
+/// Parses and validates IHDR chunk data.
+impl TryFrom<&[u8]> for ImageHeader {
+  type Error = Error;
+  fn try_from(value: &[u8]) -> Result<Self> {
+    if value.len() != IHDR_LENGTH {
+      return Err(Error::InvalidImageHeader(format!(
+        "IHDR must be {} bytes long, got {}",
+        IHDR_LENGTH,
+        value.len()
+      )));
+    }
+    let interlace_method = match value[12] {
+      0 => InterlaceMethod::None,
+      1 => InterlaceMethod::Adam7,
+      other => {
+        return Err(Error::InvalidImageHeader(format!(
+          "unknown interlace method {}",
+          other
+        )))
+      }
+    };
+    let header = ImageHeader {
+      width: u32::from_be_bytes([value[0], value[1], value[2], value[3]]),
+      height: u32::from_be_bytes([value[4], value[5], value[6], value[7]]),
+      bit_depth: value[8],
+      color_type: ColorType::try_from(value[9])?,
+      compression_method: value[10],
+      filter_method: value[11],
+      interlace_method,
+    };
+    header.validate()?;
+    Ok(header)
+  }
+}
+
+impl TryFrom<&Chunk> for ImageHeader {
+  type Error = Error;
+  fn try_from(value: &Chunk) -> Result<Self> {
+    if value.chunk_type().bytes() != *b"IHDR" {
+      return Err(Error::InvalidImageHeader(format!(
+        "expected an IHDR chunk, got {}",
+        value.chunk_type()
+      )));
+    }
+    ImageHeader::try_from(value.data())
+  }
+}
+
+impl Display for ImageHeader {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "{}x{}, {}-bit {}, {}",
+      self.width,
+      self.height,
+      self.bit_depth,
+      self.color_type,
+      match self.interlace_method {
+        InterlaceMethod::None => "non-interlaced",
+        InterlaceMethod::Adam7 => "Adam7 interlaced",
+      }
+    )
+  }
+}
+
+impl ImageHeader {
+  /// Checks the header against the constraints in the PNG spec.
+  pub fn validate(&self) -> Result<()> {
+    let invalid = |reason: String| Err(Error::InvalidImageHeader(reason));
+    if self.width == 0 || self.width > MAX_DIMENSION {
+      return invalid(format!("width {} is out of range", self.width));
+    }
+    if self.height == 0 || self.height > MAX_DIMENSION {
+      return invalid(format!("height {} is out of range", self.height));
+    }
+    if !self
+      .color_type
+      .allowed_bit_depths()
+      .contains(&self.bit_depth)
+    {
+      return invalid(format!(
+        "bit depth {} is not allowed for {} images",
+        self.bit_depth, self.color_type
+      ));
+    }
+    if self.compression_method != 0 {
+      return invalid(format!(
+        "unknown compression method {}",
+        self.compression_method
+      ));
+    }
+    if self.filter_method != 0 {
+      return invalid(format!("unknown filter method {}", self.filter_method));
+    }
+    Ok(())
+  }
+
+  /// Bits used by one pixel.
+  pub fn bits_per_pixel(&self) -> usize {
+    self.color_type.channels() * self.bit_depth as usize
+  }
+
+  /// Bytes in one unfiltered row of `width` pixels, rounded up.
+  pub fn row_bytes(&self, width: u32) -> usize {
+    (width as usize * self.bits_per_pixel()).div_ceil(8)
+  }
+
+  /// Size of the decompressed, still filtered image data, or `None` if it
+  /// does not fit in memory.
+  pub fn filtered_size(&self) -> Option<usize> {
+    match self.interlace_method {
+      InterlaceMethod::None => (self.row_bytes(self.width) + 1).checked_mul(self.height as usize),
+      InterlaceMethod::Adam7 => adam7::filtered_size(self),
+    }
+  }
+
+  /// Serializes the header into an IHDR chunk.
+  pub fn to_chunk(&self) -> Chunk {
+    let mut data = Vec::with_capacity(IHDR_LENGTH);
+    data.extend_from_slice(&self.width.to_be_bytes());
+    data.extend_from_slice(&self.height.to_be_bytes());
+    data.push(self.bit_depth);
+    data.push(self.color_type.value());
+    data.push(self.compression_method);
+    data.push(self.filter_method);
+    data.push(match self.interlace_method {
+      InterlaceMethod::None => 0,
+      InterlaceMethod::Adam7 => 1,
+    });
+    Chunk::new(ChunkType::from_str("IHDR").unwrap(), data)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::png::Png;
+
+  fn ihdr(width: u32, height: u32, bit_depth: u8, color_type: u8, interlace: u8) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(&width.to_be_bytes());
+    data.extend_from_slice(&height.to_be_bytes());
+    data.extend_from_slice(&[bit_depth, color_type, 0, 0, interlace]);
+    data
+  }
+
+  #[test]
+  fn test_parse_header() {
+    let header = ImageHeader::try_from(ihdr(640, 480, 8, 6, 1).as_slice()).unwrap();
+    assert_eq!(header.width, 640);
+    assert_eq!(header.height, 480);
+    assert_eq!(header.bit_depth, 8);
+    assert_eq!(header.color_type, ColorType::Rgba);
+    assert_eq!(header.interlace_method, InterlaceMethod::Adam7);
+    assert_eq!(header.bits_per_pixel(), 32);
+    assert_eq!(header.to_string(), "640x480, 8-bit RGBA, Adam7 interlaced");
+  }
+
+  #[test]
+  fn test_bit_depth_color_type_combinations() {
+    let allowed = [
+      (0, &[1, 2, 4, 8, 16][..]),
+      (2, &[8, 16][..]),
+      (3, &[1, 2, 4, 8][..]),
+      (4, &[8, 16][..]),
+      (6, &[8, 16][..]),
+    ];
+    for (color_type, depths) in allowed {
+      for depth in [1, 2, 3, 4, 8, 16, 32] {
+        let result = ImageHeader::try_from(ihdr(1, 1, depth, color_type, 0).as_slice());
+        assert_eq!(
+          result.is_ok(),
+          depths.contains(&depth),
+          "{} {}",
+          color_type,
+          depth
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn test_invalid_headers() {
+    let invalid = [
+      ihdr(0, 1, 8, 2, 0),
+      ihdr(1, 0, 8, 2, 0),
+      ihdr(1 << 31, 1, 8, 2, 0),
+      ihdr(1, 1, 8, 5, 0),
+      ihdr(1, 1, 8, 2, 2),
+      ihdr(1, 1, 8, 2, 0)[..12].to_vec(),
+    ];
+    for data in invalid {
+      assert!(matches!(
+        ImageHeader::try_from(data.as_slice()),
+        Err(Error::InvalidImageHeader(_))
+      ));
+    }
+
+    let mut data = ihdr(1, 1, 8, 2, 0);
+    data[10] = 1;
+    assert!(ImageHeader::try_from(data.as_slice()).is_err());
+  }
+
+  #[test]
+  fn test_row_bytes() {
+    let header = ImageHeader::try_from(ihdr(10, 1, 1, 0, 0).as_slice()).unwrap();
+    assert_eq!(header.row_bytes(10), 2);
+    let header = ImageHeader::try_from(ihdr(3, 1, 16, 2, 0).as_slice()).unwrap();
+    assert_eq!(header.row_bytes(3), 18);
+  }
+
+  #[test]
+  fn test_chunk_round_trip() {
+    let header = ImageHeader::try_from(ihdr(7, 9, 4, 3, 0).as_slice()).unwrap();
+    let chunk = header.to_chunk();
+    assert_eq!(chunk.chunk_type().to_string(), "IHDR");
+    assert_eq!(ImageHeader::try_from(&chunk).unwrap(), header);
+
+    let png = Png::from_chunks(vec![chunk]);
+    assert_eq!(png.image_header().unwrap(), header);
+  }
+}
@@ -0,0 +1,441 @@
+//! zlib (RFC 1950) and DEFLATE (RFC 1951) decompression, as used by the
+//! IDAT stream.
+
+use crate::{adler32::Adler32, Error, Result};
+
+/// Output limit used when the caller has no better bound: 256 MiB.
+pub const DEFAULT_OUTPUT_LIMIT: usize = 256 * 1024 * 1024;
+
+pub(crate) const LENGTH_BASE: [u16; 29] = [
+  3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+  163, 195, 227, 258,
+];
+pub(crate) const LENGTH_EXTRA: [u8; 29] = [
+  0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+pub(crate) const DIST_BASE: [u16; 30] = [
+  1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049,
+  3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+pub(crate) const DIST_EXTRA: [u8; 30] = [
+  0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+/// Order in which code length code lengths are stored in a dynamic block.
+pub(crate) const CODE_LENGTH_ORDER: [usize; 19] = [
+  16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+const MAX_CODE_LENGTH: usize = 15;
+
+/// Decompresses a zlib stream, failing once the output would exceed `limit`
+/// bytes. Anything after the Adler-32 trailer is ignored.
+pub fn zlib_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+  if data.len() < 2 {
+    return Err(invalid("missing zlib header"));
+  }
+  let (cmf, flg) = (data[0], data[1]);
+  if cmf & 0x0F != 8 || cmf >> 4 > 7 {
+    return Err(invalid("unsupported compression method"));
+  }
+  if !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) {
+    return Err(invalid("corrupt zlib header"));
+  }
+  if flg & 0x20 != 0 {
+    return Err(invalid("preset dictionaries are not supported"));
+  }
+
+  let mut reader = BitReader::new(&data[2..]);
+  let output = inflate_blocks(&mut reader, limit)?;
+
+  reader.align_to_byte();
+  let mut trailer = [0u8; 4];
+  for byte in &mut trailer {
+    *byte = reader.bits(8)? as u8;
+  }
+  let expected = u32::from_be_bytes(trailer);
+  let mut adler = Adler32::new();
+  adler.update(&output);
+  if adler.finish() != expected {
+    return Err(Error::AdlerMismatch {
+      expected,
+      found: adler.finish(),
+    });
+  }
+  Ok(output)
+}
+
+/// Decompresses a raw DEFLATE stream, failing once the output would exceed
+/// `limit` bytes.
+pub fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+  inflate_blocks(&mut BitReader::new(data), limit)
+}
+
+fn inflate_blocks(reader: &mut BitReader, limit: usize) -> Result<Vec<u8>> {
+  let mut output = Vec::new();
+  loop {
+    let last = reader.bits(1)? == 1;
+    match reader.bits(2)? {
+      0 => inflate_stored(reader, &mut output, limit)?,
+      1 => {
+        let (literals, distances) = fixed_tables();
+        inflate_huffman(reader, &mut output, limit, &literals, &distances)?
+      }
+      2 => {
+        let (literals, distances) = dynamic_tables(reader)?;
+        inflate_huffman(reader, &mut output, limit, &literals, &distances)?
+      }
+      _ => return Err(invalid("reserved block type")),
+    }
+    if last {
+      return Ok(output);
+    }
+  }
+}
+
+fn inflate_stored(reader: &mut BitReader, output: &mut Vec<u8>, limit: usize) -> Result<()> {
+  reader.align_to_byte();
+  let length = reader.bits(16)? as u16;
+  let complement = reader.bits(16)? as u16;
+  if length != !complement {
+    return Err(invalid("stored block length check failed"));
+  }
+  check_limit(output.len(), length as usize, limit)?;
+  output.extend_from_slice(reader.bytes(length as usize)?);
+  Ok(())
+}
+
+fn inflate_huffman(
+  reader: &mut BitReader,
+  output: &mut Vec<u8>,
+  limit: usize,
+  literals: &Huffman,
+  distances: &Huffman,
+) -> Result<()> {
+  loop {
+    let symbol = literals.decode(reader)? as usize;
+    match symbol {
+      0..=255 => {
+        check_limit(output.len(), 1, limit)?;
+        output.push(symbol as u8);
+      }
+      256 => return Ok(()),
+      _ => {
+        let index = symbol - 257;
+        if index >= LENGTH_BASE.len() {
+          return Err(invalid("invalid length symbol"));
+        }
+        let length =
+          LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+        let index = distances.decode(reader)? as usize;
+        if index >= DIST_BASE.len() {
+          return Err(invalid("invalid distance symbol"));
+        }
+        let distance = DIST_BASE[index] as usize + reader.bits(DIST_EXTRA[index] as u32)? as usize;
+        if distance > output.len() {
+          return Err(invalid("distance reaches before start of output"));
+        }
+        check_limit(output.len(), length, limit)?;
+
+        let start = output.len() - distance;
+        if distance >= length {
+          output.extend_from_within(start..start + length);
+        } else {
+          for i in 0..length {
+            output.push(output[start + i]);
+          }
+        }
+      }
+    }
+  }
+}
+
+/// The literal/length and distance codes of a fixed Huffman block.
+fn fixed_tables() -> (Huffman, Huffman) {
+  let mut lengths = [0u8; 288];
+  lengths[..144].fill(8);
+  lengths[144..256].fill(9);
+  lengths[256..280].fill(7);
+  lengths[280..].fill(8);
+  let literals = Huffman::new(&lengths).expect("fixed literal code is valid");
+  let distances = Huffman::new(&[5; 30]).expect("fixed distance code is valid");
+  (literals, distances)
+}
+
+/// Reads the code definitions at the start of a dynamic Huffman block.
+fn dynamic_tables(reader: &mut BitReader) -> Result<(Huffman, Huffman)> {
+  let literal_count = reader.bits(5)? as usize + 257;
+  let distance_count = reader.bits(5)? as usize + 1;
+  let code_length_count = reader.bits(4)? as usize + 4;
+  if literal_count > 286 || distance_count > 30 {
+    return Err(invalid("too many length or distance codes"));
+  }
+
+  let mut code_lengths = [0u8; 19];
+  for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+    code_lengths[index] = reader.bits(3)? as u8;
+  }
+  let code_lengths = Huffman::new(&code_lengths)?;
+
+  let mut lengths = vec![0u8; literal_count + distance_count];
+  let mut i = 0;
+  while i < lengths.len() {
+    let symbol = code_lengths.decode(reader)?;
+    let (value, repeat) = match symbol {
+      0..=15 => (symbol as u8, 1),
+      16 => {
+        if i == 0 {
+          return Err(invalid("repeat with no previous length"));
+        }
+        (lengths[i - 1], 3 + reader.bits(2)? as usize)
+      }
+      17 => (0, 3 + reader.bits(3)? as usize),
+      _ => (0, 11 + reader.bits(7)? as usize),
+    };
+    if i + repeat > lengths.len() {
+      return Err(invalid("code lengths overflow"));
+    }
+    lengths[i..i + repeat].fill(value);
+    i += repeat;
+  }
+
+  if lengths[256] == 0 {
+    return Err(invalid("missing end-of-block code"));
+  }
+  let literals = Huffman::new(&lengths[..literal_count])?;
+  let distances = Huffman::new(&lengths[literal_count..])?;
+  Ok((literals, distances))
+}
+
+fn check_limit(current: usize, additional: usize, limit: usize) -> Result<()> {
+  if current + additional > limit {
+    return Err(Error::OutputLimitExceeded(limit));
+  }
+  Ok(())
+}
+
+fn invalid(reason: &str) -> Error {
+  Error::InvalidCompressedData(reason.to_string())
+}
+
+/// Canonical Huffman decoding table indexed by the next `bits` input bits.
+/// Each entry holds `symbol << 4 | code length`; a length of 0 marks a bit
+/// pattern no code starts with.
+struct Huffman {
+  table: Vec<u16>,
+  bits: u32,
+}
+
+impl Huffman {
+  fn new(lengths: &[u8]) -> Result<Self> {
+    let mut counts = [0u16; MAX_CODE_LENGTH + 1];
+    for &length in lengths {
+      counts[length as usize] += 1;
+    }
+    counts[0] = 0;
+
+    let mut left: i32 = 1;
+    for &count in &counts[1..] {
+      left = (left << 1) - count as i32;
+      if left < 0 {
+        return Err(invalid("over-subscribed huffman code"));
+      }
+    }
+
+    let mut next_code = [0u16; MAX_CODE_LENGTH + 1];
+    let mut code = 0u16;
+    for bits in 1..=MAX_CODE_LENGTH {
+      code = (code + counts[bits - 1]) << 1;
+      next_code[bits] = code;
+    }
+
+    let bits = lengths.iter().copied().max().unwrap_or(0).max(1) as u32;
+    let mut table = vec![0u16; 1 << bits];
+    for (symbol, &length) in lengths.iter().enumerate() {
+      if length == 0 {
+        continue;
+      }
+      let code = next_code[length as usize];
+      next_code[length as usize] += 1;
+      // Codes are stored most significant bit first, the stream is read
+      // least significant bit first.
+      let reversed = (code.reverse_bits() >> (16 - length)) as usize;
+      let entry = (symbol as u16) << 4 | length as u16;
+      for slot in (reversed..table.len()).step_by(1 << length) {
+        table[slot] = entry;
+      }
+    }
+    Ok(Huffman { table, bits })
+  }
+
+  fn decode(&self, reader: &mut BitReader) -> Result<u16> {
+    let entry = self.table[reader.peek(self.bits) as usize];
+    let length = (entry & 0x0F) as u32;
+    if length == 0 {
+      return Err(invalid("invalid huffman code"));
+    }
+    reader.consume(length)?;
+    Ok(entry >> 4)
+  }
+}
+
+/// Reads bits least significant first, as DEFLATE packs them.
+struct BitReader<'a> {
+  data: &'a [u8],
+  position: usize,
+  buffer: u64,
+  count: u32,
+}
+
+impl<'a> BitReader<'a> {
+  fn new(data: &'a [u8]) -> Self {
+    BitReader {
+      data,
+      position: 0,
+      buffer: 0,
+      count: 0,
+    }
+  }
+
+  fn refill(&mut self) {
+    while self.count <= 56 && self.position < self.data.len() {
+      self.buffer |= (self.data[self.position] as u64) << self.count;
+      self.position += 1;
+      self.count += 8;
+    }
+  }
+
+  /// Returns the next `n` bits without consuming them, padded with zeros
+  /// past the end of the input.
+  fn peek(&mut self, n: u32) -> u32 {
+    if self.count < n {
+      self.refill();
+    }
+    (self.buffer & ((1u64 << n) - 1)) as u32
+  }
+
+  fn consume(&mut self, n: u32) -> Result<()> {
+    if self.count < n {
+      return Err(invalid("unexpected end of stream"));
+    }
+    self.buffer >>= n;
+    self.count -= n;
+    Ok(())
+  }
+
+  fn bits(&mut self, n: u32) -> Result<u32> {
+    let value = self.peek(n);
+    self.consume(n)?;
+    Ok(value)
+  }
+
+  fn align_to_byte(&mut self) {
+    let partial = self.count % 8;
+    self.buffer >>= partial;
+    self.count -= partial;
+  }
+
+  /// Takes `n` whole bytes; the reader must be byte-aligned.
+  fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+    // Hand back any whole bytes still sitting in the bit buffer.
+    self.position -= (self.count / 8) as usize;
+    self.buffer = 0;
+    self.count = 0;
+    let end = self.position + n;
+    if end > self.data.len() {
+      return Err(invalid("unexpected end of stream"));
+    }
+    let bytes = &self.data[self.position..end];
+    self.position = end;
+    Ok(bytes)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const STORED: [u8; 23] = [
+    120, 1, 1, 12, 0, 243, 255, 104, 101, 108, 108, 111, 32, 115, 116, 111, 114, 101, 100, 30, 213,
+    4, 198,
+  ];
+  const FIXED: [u8; 16] = [
+    120, 218, 203, 72, 205, 201, 201, 87, 200, 64, 39, 1, 104, 3, 8, 177,
+  ];
+  const DYNAMIC: [u8; 115] = [
+    120, 218, 237, 209, 193, 13, 131, 48, 20, 4, 209, 59, 85, 252, 18, 248, 73, 128, 80, 144, 163,
+    88, 178, 32, 82, 76, 255, 136, 2, 166, 2, 230, 60, 167, 221, 215, 234, 86, 98, 140, 253, 19,
+    253, 91, 162, 151, 127, 143, 186, 253, 142, 62, 180, 43, 36, 133, 7, 133, 39, 133, 23, 133,
+    137, 194, 76, 97, 161, 240, 166, 176, 226, 64, 158, 142, 219, 19, 199, 39, 174, 79, 156, 159,
+    184, 63, 241, 0, 189, 244, 210, 75, 47, 189, 244, 210, 75, 47, 189, 244, 210, 75, 47, 189, 244,
+    210, 75, 47, 189, 110, 239, 117, 2, 167, 233, 188, 241,
+  ];
+
+  fn dynamic_text() -> Vec<u8> {
+    (0..200)
+      .map(|i| format!("line {} of the test input\n", i % 17))
+      .collect::<String>()
+      .into_bytes()
+  }
+
+  #[test]
+  fn test_stored_block() {
+    let output = zlib_decompress(&STORED, DEFAULT_OUTPUT_LIMIT).unwrap();
+    assert_eq!(output, b"hello stored");
+  }
+
+  #[test]
+  fn test_fixed_block() {
+    let output = zlib_decompress(&FIXED, DEFAULT_OUTPUT_LIMIT).unwrap();
+    assert_eq!(output, b"hello hello hello hello");
+  }
+
+  #[test]
+  fn test_dynamic_block() {
+    let output = zlib_decompress(&DYNAMIC, DEFAULT_OUTPUT_LIMIT).unwrap();
+    assert_eq!(output, dynamic_text());
+  }
+
+  #[test]
+  fn test_raw_inflate() {
+    let output = inflate(&FIXED[2..FIXED.len() - 4], DEFAULT_OUTPUT_LIMIT).unwrap();
+    assert_eq!(output, b"hello hello hello hello");
+  }
+
+  #[test]
+  fn test_output_limit() {
+    assert!(matches!(
+      zlib_decompress(&DYNAMIC, 1000),
+      Err(Error::OutputLimitExceeded(1000))
+    ));
+    assert!(matches!(
+      zlib_decompress(&STORED, 11),
+      Err(Error::OutputLimitExceeded(11))
+    ));
+    assert!(zlib_decompress(&STORED, 12).is_ok());
+  }
+
+  #[test]
+  fn test_adler_mismatch() {
+    let mut data = FIXED;
+    data[15] ^= 1;
+    assert!(matches!(
+      zlib_decompress(&data, DEFAULT_OUTPUT_LIMIT),
+      Err(Error::AdlerMismatch { .. })
+    ));
+  }
+
+  #[test]
+  fn test_truncated_and_corrupt_streams() {
+    for end in 0..DYNAMIC.len() - 4 {
+      assert!(zlib_decompress(&DYNAMIC[..end], DEFAULT_OUTPUT_LIMIT).is_err());
+    }
+    assert!(matches!(
+      zlib_decompress(&[0x78, 0x9C, 0x07], DEFAULT_OUTPUT_LIMIT),
+      Err(Error::InvalidCompressedData(_))
+    ));
+    assert!(matches!(
+      zlib_decompress(&[0x78, 0x00], DEFAULT_OUTPUT_LIMIT),
+      Err(Error::InvalidCompressedData(_))
+    ));
+  }
+}
@@ -0,0 +1,435 @@
+//! X25519 key pairs for encrypting messages to a recipient, Ed25519 key
+//! pairs for signing them, and the one-line text files they are kept in:
+//!
+//! ```text
+//! pngme-x25519-secret-key 4b66e9d4...
+//! pngme-x25519-public-key e5210f12...
+//! pngme-ed25519-signing-key 9d61b19d...
+//! pngme-ed25519-verifying-key d75a9801...
+//! ```
+
+use std::{fmt::Display, str::FromStr};
+
+use ed25519_dalek::{Signature, Signer, SIGNATURE_LENGTH};
+use x25519_dalek::StaticSecret;
+
+use crate::{crypto, Error, Result};
+
+const SECRET_LABEL: &str = "pngme-x25519-secret-key";
+const PUBLIC_LABEL: &str = "pngme-x25519-public-key";
+const SIGNING_LABEL: &str = "pngme-ed25519-signing-key";
+const VERIFYING_LABEL: &str = "pngme-ed25519-verifying-key";
+
+/// A short hash identifying a public key, stored next to ciphertexts so a
+/// recipient can tell which key a message needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint([u8; 8]);
+
+impl Fingerprint {
+  pub(crate) const SIZE: usize = 8;
+
+  /// Hashes `label` and `key` together so keys of different kinds never
+  /// share a fingerprint.
+  pub(crate) fn of(label: &str, key: &[u8]) -> Self {
+    let hash = crypto::blake2b_256(&[label.as_bytes(), key]);
+    Fingerprint(hash[..Self::SIZE].try_into().unwrap())
+  }
+
+  pub fn from_bytes(bytes: [u8; 8]) -> Self {
+    Fingerprint(bytes)
+  }
+
+  pub fn bytes(&self) -> [u8; 8] {
+    self.0
+  }
+}
+
+/// Four groups of four hex digits, e.g. `3f2a:91bc:0d4e:7781`.
+impl Display for Fingerprint {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    for (i, pair) in self.0.chunks(2).enumerate() {
+      if i > 0 {
+        write!(f, ":")?;
+      }
+      write!(f, "{}", to_hex(pair))?;
+    }
+    Ok(())
+  }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct SecretKey {
+  bytes: [u8; 32],
+}
+
+/// Never prints the key itself.
+impl std::fmt::Debug for SecretKey {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "SecretKey({})", self.public_key().fingerprint())
+  }
+}
+
+impl FromStr for SecretKey {
+  type Err = Error;
+  fn from_str(s: &str) -> Result<Self> {
+    Ok(SecretKey::from_bytes(parse_key_line(s, SECRET_LABEL)?))
+  }
+}
+
+impl SecretKey {
+  /// Draws a new key from the operating system's random source.
+  pub fn generate() -> Result<Self> {
+    let mut bytes = [0u8; 32];
+    crypto::random_bytes(&mut bytes)?;
+    Ok(SecretKey::from_bytes(bytes))
+  }
+
+  pub fn from_bytes(bytes: [u8; 32]) -> Self {
+    SecretKey { bytes }
+  }
+
+  pub fn public_key(&self) -> PublicKey {
+    PublicKey::from_bytes(x25519_dalek::PublicKey::from(&self.dalek()).to_bytes())
+  }
+
+  pub(crate) fn diffie_hellman(&self, public: &PublicKey) -> [u8; 32] {
+    self
+      .dalek()
+      .diffie_hellman(&x25519_dalek::PublicKey::from(public.bytes))
+      .to_bytes()
+  }
+
+  fn dalek(&self) -> StaticSecret {
+    StaticSecret::from(self.bytes)
+  }
+
+  /// The key file line, ending in a newline.
+  pub fn to_file_contents(&self) -> String {
+    format!("{} {}\n", SECRET_LABEL, to_hex(&self.bytes))
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicKey {
+  bytes: [u8; 32],
+}
+
+impl FromStr for PublicKey {
+  type Err = Error;
+  fn from_str(s: &str) -> Result<Self> {
+    Ok(PublicKey::from_bytes(parse_key_line(s, PUBLIC_LABEL)?))
+  }
+}
+
+impl PublicKey {
+  pub fn from_bytes(bytes: [u8; 32]) -> Self {
+    PublicKey { bytes }
+  }
+
+  pub fn bytes(&self) -> [u8; 32] {
+    self.bytes
+  }
+
+  pub fn fingerprint(&self) -> Fingerprint {
+    Fingerprint::of(PUBLIC_LABEL, &self.bytes)
+  }
+
+  /// The key file line, ending in a newline.
+  pub fn to_file_contents(&self) -> String {
+    format!("{} {}\n", PUBLIC_LABEL, to_hex(&self.bytes))
+  }
+}
+
+/// The secret half of an Ed25519 key pair: the 32-byte seed of RFC 8032.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SigningKey {
+  seed: [u8; 32],
+}
+
+/// Never prints the key itself.
+impl std::fmt::Debug for SigningKey {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "SigningKey({})", self.verifying_key().fingerprint())
+  }
+}
+
+impl FromStr for SigningKey {
+  type Err = Error;
+  fn from_str(s: &str) -> Result<Self> {
+    Ok(SigningKey::from_bytes(parse_key_line(s, SIGNING_LABEL)?))
+  }
+}
+
+impl SigningKey {
+  /// Draws a new key from the operating system's random source.
+  pub fn generate() -> Result<Self> {
+    let mut seed = [0u8; 32];
+    crypto::random_bytes(&mut seed)?;
+    Ok(SigningKey::from_bytes(seed))
+  }
+
+  pub fn from_bytes(seed: [u8; 32]) -> Self {
+    SigningKey { seed }
+  }
+
+  pub fn verifying_key(&self) -> VerifyingKey {
+    VerifyingKey::from_bytes(self.dalek().verifying_key().to_bytes())
+  }
+
+  pub fn sign(&self, message: &[u8]) -> [u8; SIGNATURE_LENGTH] {
+    self.dalek().sign(message).to_bytes()
+  }
+
+  fn dalek(&self) -> ed25519_dalek::SigningKey {
+    ed25519_dalek::SigningKey::from_bytes(&self.seed)
+  }
+
+  /// The key file line, ending in a newline.
+  pub fn to_file_contents(&self) -> String {
+    format!("{} {}\n", SIGNING_LABEL, to_hex(&self.seed))
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyingKey {
+  bytes: [u8; 32],
+}
+
+impl FromStr for VerifyingKey {
+  type Err = Error;
+  fn from_str(s: &str) -> Result<Self> {
+    Ok(VerifyingKey::from_bytes(parse_key_line(
+      s,
+      VERIFYING_LABEL,
+    )?))
+  }
+}
+
+impl VerifyingKey {
+  pub fn from_bytes(bytes: [u8; 32]) -> Self {
+    VerifyingKey { bytes }
+  }
+
+  pub fn bytes(&self) -> [u8; 32] {
+    self.bytes
+  }
+
+  pub fn fingerprint(&self) -> Fingerprint {
+    Fingerprint::of(VERIFYING_LABEL, &self.bytes)
+  }
+
+  /// Whether `signature` was made over `message` by this key's owner.
+  /// Keys that are not valid curve points never verify anything.
+  pub fn verify(&self, message: &[u8], signature: &[u8; SIGNATURE_LENGTH]) -> bool {
+    ed25519_dalek::VerifyingKey::from_bytes(&self.bytes).is_ok_and(|key| {
+      key
+        .verify_strict(message, &Signature::from_bytes(signature))
+        .is_ok()
+    })
+  }
+
+  /// The key file line, ending in a newline.
+  pub fn to_file_contents(&self) -> String {
+    format!("{} {}\n", VERIFYING_LABEL, to_hex(&self.bytes))
+  }
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Parses `<label> <64 hex digits>`, allowing surrounding whitespace.
+pub(crate) fn parse_key_line(text: &str, label: &str) -> Result<[u8; 32]> {
+  let mut words = text.split_whitespace();
+  let found = words.next().unwrap_or_default();
+  if found != label {
+    return Err(Error::InvalidKey(format!(
+      "expected a {} line, found '{}'",
+      label, found
+    )));
+  }
+  let hex = words
+    .next()
+    .ok_or_else(|| Error::InvalidKey("missing key bytes".to_string()))?;
+  if let Some(extra) = words.next() {
+    return Err(Error::InvalidKey(format!(
+      "unexpected '{}' after the key",
+      extra
+    )));
+  }
+  if hex.len() != 64 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+    return Err(Error::InvalidKey(
+      "key must be 64 hexadecimal digits".to_string(),
+    ));
+  }
+
+  let mut bytes = [0u8; 32];
+  for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+    *byte = u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap();
+  }
+  Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_key_file_round_trip() {
+    let secret = SecretKey::generate().unwrap();
+    let parsed: SecretKey = secret.to_file_contents().parse().unwrap();
+    assert_eq!(parsed, secret);
+
+    let public = secret.public_key();
+    let parsed: PublicKey = public.to_file_contents().parse().unwrap();
+    assert_eq!(parsed, public);
+    assert_eq!(parsed.fingerprint(), public.fingerprint());
+  }
+
+  #[test]
+  fn test_signing_key_file_round_trip() {
+    let signing = SigningKey::generate().unwrap();
+    let parsed: SigningKey = signing.to_file_contents().parse().unwrap();
+    assert_eq!(parsed, signing);
+
+    let verifying = signing.verifying_key();
+    let parsed: VerifyingKey = verifying.to_file_contents().parse().unwrap();
+    assert_eq!(parsed, verifying);
+    assert!(parsed.verify(b"message", &signing.sign(b"message")));
+  }
+
+  #[test]
+  fn test_key_kinds_are_not_interchangeable() {
+    let signing = SigningKey::from_bytes([1; 32]);
+    assert!(signing.to_file_contents().parse::<SecretKey>().is_err());
+    assert!(signing
+      .verifying_key()
+      .to_file_contents()
+      .parse::<PublicKey>()
+      .is_err());
+    assert_ne!(
+      signing.verifying_key().fingerprint(),
+      PublicKey::from_bytes(signing.verifying_key().bytes()).fingerprint()
+    );
+
+    let secret = SecretKey::generate().unwrap();
+    assert!(matches!(
+      secret.to_file_contents().parse::<PublicKey>(),
+      Err(Error::InvalidKey(_))
+    ));
+    assert!(matches!(
+      secret.public_key().to_file_contents().parse::<SecretKey>(),
+      Err(Error::InvalidKey(_))
+    ));
+  }
+
+  #[test]
+  fn test_malformed_key_files() {
+    for text in [
+      "",
+      "pngme-x25519-public-key",
+      "pngme-x25519-public-key 1234",
+      "pngme-x25519-public-key zz46e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4",
+      "pngme-x25519-public-key a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4 x",
+    ] {
+      assert!(text.parse::<PublicKey>().is_err(), "{:?}", text);
+    }
+  }
+
+  #[test]
+  fn test_fingerprint_display() {
+    let fingerprint = Fingerprint::from_bytes([0x3f, 0x2a, 0x91, 0xbc, 0x0d, 0x4e, 0x77, 0x81]);
+    assert_eq!(fingerprint.to_string(), "3f2a:91bc:0d4e:7781");
+  }
+
+  #[test]
+  fn test_key_agreement_is_stable() {
+    // RFC 7748, section 6.1.
+    let key = |text: &str| -> [u8; 32] { crate::crypto::tests::hex(text).try_into().unwrap() };
+    let alice = SecretKey::from_bytes(key(
+      "77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a",
+    ));
+    let bob = SecretKey::from_bytes(key(
+      "5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb",
+    ));
+    assert_eq!(
+      alice.public_key().bytes(),
+      key("8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a")
+    );
+    assert_eq!(
+      bob.public_key().bytes(),
+      key("de9edb7d7b7dc1b4d35b61c2ece435373f8343c85b78674dadfc7e146f882b4f")
+    );
+    let shared = key("4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742");
+    assert_eq!(alice.diffie_hellman(&bob.public_key()), shared);
+    assert_eq!(bob.diffie_hellman(&alice.public_key()), shared);
+
+    // A key made before key agreement moved to x25519-dalek.
+    assert_eq!(
+      SecretKey::from_bytes([2; 32])
+        .public_key()
+        .fingerprint()
+        .to_string(),
+      "197e:3b4d:2100:1014"
+    );
+  }
+
+  #[test]
+  fn test_signing_is_stable() {
+    // RFC 8032, section 7.1, test 1.
+    let seed =
+      crate::crypto::tests::hex("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60");
+    let signing = SigningKey::from_bytes(seed.try_into().unwrap());
+    let verifying = signing.verifying_key();
+    assert_eq!(
+      to_hex(&verifying.bytes()),
+      "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a"
+    );
+    let signature = signing.sign(b"");
+    assert_eq!(
+      to_hex(&signature),
+      "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555\
+       fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b"
+    );
+    assert!(verifying.verify(b"", &signature));
+
+    // A key made before signing moved to ed25519-dalek.
+    let signing = SigningKey::from_bytes([3; 32]);
+    assert_eq!(
+      to_hex(&signing.verifying_key().bytes()),
+      "ed4928c628d1c2c6eae90338905995612959273a5c63f93636c14614ac8737d1"
+    );
+    assert_eq!(
+      to_hex(&signing.sign(b"pngme")),
+      "bbe5477a497ac568f2172ec0a5469b2de6588e36b0323e39be1e679032f5932f\
+       112b8eff0ecef23c7ea952cfda20879044d63ee75635ef024e499effd4615908"
+    );
+  }
+
+  #[test]
+  fn test_verify_rejects_tampering() {
+    let signing = SigningKey::from_bytes([7; 32]);
+    let verifying = signing.verifying_key();
+    let signature = signing.sign(b"hello");
+    assert!(verifying.verify(b"hello", &signature));
+    assert!(!verifying.verify(b"hellp", &signature));
+    assert!(!SigningKey::from_bytes([8; 32])
+      .verifying_key()
+      .verify(b"hello", &signature));
+    for i in [0, 31, 32, 63] {
+      let mut bad = signature;
+      bad[i] ^= 1;
+      assert!(!verifying.verify(b"hello", &bad), "byte {}", i);
+    }
+    // Not a point on the curve.
+    assert!(!VerifyingKey::from_bytes([0xFF; 32]).verify(b"hello", &signature));
+  }
+
+  #[test]
+  fn test_debug_hides_secret() {
+    let secret = SecretKey::from_bytes([0xAB; 32]);
+    assert!(!format!("{:?}", secret).contains("abab"));
+    let signing = SigningKey::from_bytes([0xAB; 32]);
+    assert!(!format!("{:?}", signing).contains("abab"));
+  }
+}
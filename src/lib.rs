@@ -0,0 +1,48 @@
+//! Hide messages inside PNG files.
+//!
+//! The building blocks are [`ChunkType`], [`Chunk`] and [`Png`]; the
+//! [`commands`] module implements the `pngme` subcommands on top of them.
+
+pub mod adam7;
+mod adler32;
+pub mod args;
+pub mod attachment;
+mod bytes;
+pub mod chunk;
+pub mod chunk_ref;
+pub mod chunk_type;
+pub mod commands;
+pub mod compression;
+mod crc;
+mod crypto;
+pub mod deflate;
+pub mod encoder;
+pub mod encryption;
+pub mod envelope;
+pub mod error;
+pub mod filter;
+pub mod fragment;
+pub mod image_header;
+pub mod inflate;
+pub mod keys;
+pub mod lsb;
+pub mod manifest;
+pub mod pixel_buffer;
+pub mod png;
+pub mod reader;
+pub mod signature;
+pub mod writer;
+
+pub use chunk::Chunk;
+pub use chunk_ref::{ChunkRef, ChunkRefs};
+pub use chunk_type::ChunkType;
+pub use encoder::Encoder;
+pub use envelope::Envelope;
+pub use error::Error;
+pub use image_header::{ColorType, ImageHeader, InterlaceMethod};
+pub use pixel_buffer::{Pixel, PixelBuffer};
+pub use png::Png;
+pub use reader::ChunkReader;
+pub use writer::{ChunkWriter, StreamEditor};
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,425 @@
+//! Hides messages in the least significant bits of pixel samples, where
+//! they survive tools that strip ancillary chunks.
+//!
+//! The embedded frame is a 4-byte big-endian message length, the message and
+//! its CRC-32, written most significant bit first into the low bits of each
+//! selected sample in row-major order. Only 8- and 16-bit truecolor and
+//! grayscale images are supported; for 16-bit samples only the low byte is
+//! touched.
+
+use std::{fmt::Display, str::FromStr};
+
+use crate::{crc, image_header::ColorType, pixel_buffer::PixelBuffer, Error, Result};
+
+/// Bytes the length and checksum add to every message.
+pub const FRAME_OVERHEAD: usize = 8;
+
+/// Which samples of each pixel carry message bits. For grayscale images any
+/// of red, green or blue selects the gray sample.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channels {
+  pub red: bool,
+  pub green: bool,
+  pub blue: bool,
+  pub alpha: bool,
+}
+
+impl Default for Channels {
+  fn default() -> Self {
+    Channels {
+      red: true,
+      green: true,
+      blue: true,
+      alpha: false,
+    }
+  }
+}
+
+/// Parses a selection such as `rgb` or `a` from the letters `r`, `g`, `b`
+/// and `a`.
+impl FromStr for Channels {
+  type Err = Error;
+  fn from_str(s: &str) -> Result<Self> {
+    let mut channels = Channels {
+      red: false,
+      green: false,
+      blue: false,
+      alpha: false,
+    };
+    for letter in s.chars() {
+      let channel = match letter {
+        'r' => &mut channels.red,
+        'g' => &mut channels.green,
+        'b' => &mut channels.blue,
+        'a' => &mut channels.alpha,
+        other => {
+          return Err(Error::Usage(format!(
+            "unknown channel '{}': expected some of r, g, b, a",
+            other
+          )))
+        }
+      };
+      *channel = true;
+    }
+    if s.is_empty() {
+      return Err(Error::Usage("no channels selected".to_string()));
+    }
+    Ok(channels)
+  }
+}
+
+impl Display for Channels {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    for (selected, letter) in [
+      (self.red, 'r'),
+      (self.green, 'g'),
+      (self.blue, 'b'),
+      (self.alpha, 'a'),
+    ] {
+      if selected {
+        write!(f, "{}", letter)?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// How a message is spread over the pixels. Decoding needs the same options
+/// that were used for encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LsbOptions {
+  /// Low bits used per selected sample, 1 to 8.
+  pub bits_per_channel: u8,
+  pub channels: Channels,
+}
+
+impl Default for LsbOptions {
+  fn default() -> Self {
+    LsbOptions {
+      bits_per_channel: 1,
+      channels: Channels::default(),
+    }
+  }
+}
+
+/// Number of message bytes that fit in `pixels`, not counting the frame.
+pub fn capacity(pixels: &PixelBuffer, options: &LsbOptions) -> Result<usize> {
+  let slots = sample_offsets(pixels, options)?.len();
+  Ok((slots * options.bits_per_channel as usize / 8).saturating_sub(FRAME_OVERHEAD))
+}
+
+/// Writes `message` into the low bits of the selected samples.
+pub fn embed(pixels: &mut PixelBuffer, message: &[u8], options: &LsbOptions) -> Result<()> {
+  let offsets = sample_offsets(pixels, options)?;
+  let capacity =
+    (offsets.len() * options.bits_per_channel as usize / 8).saturating_sub(FRAME_OVERHEAD);
+  if message.len() > capacity || message.len() > u32::MAX as usize {
+    return Err(Error::MessageTooLarge {
+      size: message.len(),
+      capacity,
+    });
+  }
+
+  let mut frame = Vec::with_capacity(message.len() + FRAME_OVERHEAD);
+  frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+  frame.extend_from_slice(message);
+  frame.extend_from_slice(&checksum(message).to_be_bytes());
+
+  let bits = options.bits_per_channel;
+  let mask = low_mask(bits);
+  let data = pixels.data_mut();
+  let mut reader = BitCursor::new(&frame);
+  for &offset in &offsets {
+    if reader.is_done() {
+      break;
+    }
+    data[offset] = (data[offset] & !mask) | reader.take(bits);
+  }
+  Ok(())
+}
+
+/// Reads back a message written by [`embed`] with the same options.
+pub fn extract(pixels: &PixelBuffer, options: &LsbOptions) -> Result<Vec<u8>> {
+  let offsets = sample_offsets(pixels, options)?;
+  let bits = options.bits_per_channel;
+  let mask = low_mask(bits);
+  let data = pixels.data();
+  let mut writer = BitSink::default();
+  let mut slots = offsets.iter().map(|&offset| data[offset] & mask);
+
+  writer.fill(&mut slots, bits, 4);
+  if writer.bytes.len() < 4 {
+    return Err(Error::NoHiddenMessage);
+  }
+  let length = u32::from_be_bytes(writer.bytes[..4].try_into().unwrap()) as usize;
+  let available = offsets.len() * bits as usize / 8;
+  if length + FRAME_OVERHEAD > available {
+    return Err(Error::NoHiddenMessage);
+  }
+
+  writer.fill(&mut slots, bits, length + FRAME_OVERHEAD);
+  let message = &writer.bytes[4..4 + length];
+  let stored = u32::from_be_bytes(writer.bytes[4 + length..][..4].try_into().unwrap());
+  if stored != checksum(message) {
+    return Err(Error::NoHiddenMessage);
+  }
+  Ok(message.to_vec())
+}
+
+/// Byte offsets in the pixel data of every sample that carries bits, in
+/// embedding order.
+fn sample_offsets(pixels: &PixelBuffer, options: &LsbOptions) -> Result<Vec<usize>> {
+  if !(1..=8).contains(&options.bits_per_channel) {
+    return Err(Error::Usage(format!(
+      "bits per channel must be between 1 and 8, got {}",
+      options.bits_per_channel
+    )));
+  }
+  let color_type = pixels.color_type();
+  let bit_depth = pixels.bit_depth();
+  if color_type == ColorType::Indexed || bit_depth < 8 {
+    return Err(Error::InvalidImageData(format!(
+      "cannot hide bits in {}-bit {} images",
+      bit_depth, color_type
+    )));
+  }
+
+  let channels = options.channels;
+  let color = channels.red || channels.green || channels.blue;
+  let selected: Vec<usize> = match color_type {
+    ColorType::Grayscale => vec![0].into_iter().filter(|_| color).collect(),
+    ColorType::GrayscaleAlpha => [(color, 0), (channels.alpha, 1)]
+      .iter()
+      .filter_map(|&(on, index)| on.then_some(index))
+      .collect(),
+    _ => [
+      (channels.red, 0),
+      (channels.green, 1),
+      (channels.blue, 2),
+      (channels.alpha, 3),
+    ]
+    .iter()
+    .filter_map(|&(on, index)| (on && index < color_type.channels()).then_some(index))
+    .collect(),
+  };
+  if channels.alpha && !matches!(color_type, ColorType::GrayscaleAlpha | ColorType::Rgba) {
+    return Err(Error::InvalidImageData(format!(
+      "{} images have no alpha channel",
+      color_type
+    )));
+  }
+  if selected.is_empty() {
+    return Err(Error::InvalidImageData(format!(
+      "channels '{}' select nothing in {} images",
+      channels, color_type
+    )));
+  }
+
+  let sample_bytes = bit_depth as usize / 8;
+  let pixel_bytes = color_type.channels() * sample_bytes;
+  let pixel_count = pixels.data().len() / pixel_bytes;
+  Ok(
+    (0..pixel_count)
+      .flat_map(|pixel| {
+        selected
+          .iter()
+          .map(move |&channel| pixel * pixel_bytes + (channel + 1) * sample_bytes - 1)
+      })
+      .collect(),
+  )
+}
+
+fn checksum(message: &[u8]) -> u32 {
+  let mut hasher = crc::Crc32::new();
+  hasher.update(message);
+  hasher.finish()
+}
+
+fn low_mask(bits: u8) -> u8 {
+  (0xFFu16 >> (8 - bits)) as u8
+}
+
+/// Reads a byte slice `bits` at a time, most significant bit first, padding
+/// the end with zeros.
+struct BitCursor<'a> {
+  bytes: &'a [u8],
+  position: usize,
+}
+
+impl<'a> BitCursor<'a> {
+  fn new(bytes: &'a [u8]) -> Self {
+    BitCursor { bytes, position: 0 }
+  }
+
+  fn is_done(&self) -> bool {
+    self.position >= self.bytes.len() * 8
+  }
+
+  fn take(&mut self, bits: u8) -> u8 {
+    let mut value = 0;
+    for _ in 0..bits {
+      let bit = self
+        .bytes
+        .get(self.position / 8)
+        .map_or(0, |byte| (byte >> (7 - self.position % 8)) & 1);
+      value = (value << 1) | bit;
+      self.position += 1;
+    }
+    value
+  }
+}
+
+/// Collects values `bits` wide into bytes, most significant bit first.
+#[derive(Default)]
+struct BitSink {
+  bytes: Vec<u8>,
+  pending: u16,
+  pending_bits: u8,
+}
+
+impl BitSink {
+  /// Pulls slots until `target` whole bytes have been collected or the slots
+  /// run out.
+  fn fill(&mut self, slots: &mut impl Iterator<Item = u8>, bits: u8, target: usize) {
+    while self.bytes.len() < target {
+      let Some(value) = slots.next() else {
+        return;
+      };
+      for i in (0..bits).rev() {
+        self.pending = (self.pending << 1) | ((value >> i) & 1) as u16;
+        self.pending_bits += 1;
+        if self.pending_bits == 8 {
+          self.bytes.push(self.pending as u8);
+          self.pending = 0;
+          self.pending_bits = 0;
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn image(color_type: ColorType, bit_depth: u8) -> PixelBuffer {
+    let size = 16 * 16 * color_type.channels() * bit_depth as usize / 8;
+    let data = (0..size).map(|i| (i * 37) as u8).collect();
+    PixelBuffer::new(16, 16, color_type, bit_depth, data).unwrap()
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let message = b"meet me at noon";
+    for (color_type, bit_depth) in [
+      (ColorType::Rgb, 8),
+      (ColorType::Rgba, 16),
+      (ColorType::Grayscale, 8),
+      (ColorType::GrayscaleAlpha, 16),
+    ] {
+      for bits_per_channel in [1, 3, 8] {
+        let options = LsbOptions {
+          bits_per_channel,
+          channels: Channels::default(),
+        };
+        let mut pixels = image(color_type, bit_depth);
+        embed(&mut pixels, message, &options).unwrap();
+        assert_eq!(extract(&pixels, &options).unwrap(), message);
+      }
+    }
+  }
+
+  #[test]
+  fn test_only_low_bits_change() {
+    let original = image(ColorType::Rgba, 16);
+    let mut pixels = original.clone();
+    let options = LsbOptions {
+      bits_per_channel: 2,
+      channels: "ga".parse().unwrap(),
+    };
+    embed(&mut pixels, &[0xFF; 40], &options).unwrap();
+
+    for (i, (&before, &after)) in original.data().iter().zip(pixels.data()).enumerate() {
+      let sample = i / 2 % 4;
+      if i % 2 == 1 && (sample == 1 || sample == 3) {
+        assert_eq!(before & !0b11, after & !0b11);
+      } else {
+        assert_eq!(before, after);
+      }
+    }
+  }
+
+  #[test]
+  fn test_extract_from_tiny_image() {
+    // 36 one-bit slots: room for the length but not the checksum.
+    let pixels = PixelBuffer::new(6, 6, ColorType::Grayscale, 8, vec![0; 36]).unwrap();
+    assert!(matches!(
+      extract(&pixels, &LsbOptions::default()),
+      Err(Error::NoHiddenMessage)
+    ));
+  }
+
+  #[test]
+  fn test_capacity() {
+    let pixels = image(ColorType::Rgb, 8);
+    // 256 pixels * 3 samples * 1 bit = 96 bytes, minus the frame.
+    assert_eq!(capacity(&pixels, &LsbOptions::default()).unwrap(), 88);
+
+    let mut pixels = pixels;
+    assert!(embed(&mut pixels, &[0; 88], &LsbOptions::default()).is_ok());
+    assert!(matches!(
+      embed(&mut pixels, &[0; 89], &LsbOptions::default()),
+      Err(Error::MessageTooLarge {
+        size: 89,
+        capacity: 88
+      })
+    ));
+  }
+
+  #[test]
+  fn test_wrong_options_find_nothing() {
+    let mut pixels = image(ColorType::Rgb, 8);
+    embed(&mut pixels, b"secret", &LsbOptions::default()).unwrap();
+    let other = LsbOptions {
+      bits_per_channel: 2,
+      channels: Channels::default(),
+    };
+    assert!(matches!(
+      extract(&pixels, &other),
+      Err(Error::NoHiddenMessage)
+    ));
+    assert!(matches!(
+      extract(&image(ColorType::Rgb, 8), &LsbOptions::default()),
+      Err(Error::NoHiddenMessage)
+    ));
+  }
+
+  #[test]
+  fn test_unsupported_images() {
+    let options = LsbOptions::default();
+    let mut gray = PixelBuffer::new(8, 1, ColorType::Grayscale, 1, vec![0]).unwrap();
+    assert!(embed(&mut gray, b"x", &options).is_err());
+
+    let alpha = LsbOptions {
+      bits_per_channel: 1,
+      channels: "a".parse().unwrap(),
+    };
+    assert!(capacity(&image(ColorType::Rgb, 8), &alpha).is_err());
+    assert!(capacity(
+      &image(ColorType::Rgb, 8),
+      &LsbOptions {
+        bits_per_channel: 9,
+        ..options
+      }
+    )
+    .is_err());
+  }
+
+  #[test]
+  fn test_parse_channels() {
+    assert_eq!("rgb".parse::<Channels>().unwrap(), Channels::default());
+    assert_eq!("bar".parse::<Channels>().unwrap().to_string(), "rba");
+    assert!("".parse::<Channels>().is_err());
+    assert!("rgx".parse::<Channels>().is_err());
+  }
+}
@@ -1,13 +1,28 @@
-mod args;
-mod chunk;
-mod chunk_type;
-mod commands;
-mod png;
+use rust_pngme::{
+  args::{self, PngMeArgs},
+  commands, Error, Result,
+};
 
-pub type Error = Box<dyn std::error::Error>;
-pub type Result<T> = std::result::Result<T, Error>;
+fn main() {
+  if let Err(e) = run() {
+    eprintln!("error: {}", e);
+    if let Error::Usage(_) = e {
+      eprintln!("\n{}", args::USAGE);
+      std::process::exit(2);
+    }
+    std::process::exit(1);
+  }
+}
 
-fn main() -> Result<()> {
-  // Ok(())
-  todo!()
+fn run() -> Result<()> {
+  match PngMeArgs::parse(std::env::args().skip(1))? {
+    PngMeArgs::Encode(args) => commands::encode(args),
+    PngMeArgs::Decode(args) => commands::decode(args),
+    PngMeArgs::Verify(args) => commands::verify(args),
+    PngMeArgs::Remove(args) => commands::remove(args),
+    PngMeArgs::ListMessages(args) => commands::list_messages(args),
+    PngMeArgs::Print(args) => commands::print_chunks(args),
+    PngMeArgs::Keygen(args) => commands::keygen(args),
+    PngMeArgs::KeyInfo(args) => commands::key_info(args),
+  }
 }
@@ -0,0 +1,291 @@
+//! Names for stored messages, kept in a single `maNf` chunk.
+//!
+//! Several messages can share a chunk type, so a name points at one of them
+//! by the hash of its stored payload: the same BLAKE2b-256 hash a fragmented
+//! message carries in every fragment (see [`fragment`]), which lets a named
+//! fragmented message be found without reassembling anything. The chunk
+//! data is laid out as:
+//!
+//! | bytes | field                                          |
+//! |-------|------------------------------------------------|
+//! | 1     | version, currently `1`                         |
+//! | 2     | entry count, big-endian                        |
+//!
+//! followed by each entry:
+//!
+//! | bytes | field                                          |
+//! |-------|------------------------------------------------|
+//! | 1     | name length                                    |
+//! | n     | name, UTF-8                                    |
+//! | 4     | chunk type the message is stored in            |
+//! | 8     | stored payload size, big-endian                |
+//! | 32    | BLAKE2b-256 of the stored payload              |
+//!
+//! [`fragment`]: crate::fragment
+
+use crate::{
+  bytes::take,
+  chunk::Chunk,
+  chunk_type::ChunkType,
+  fragment::{self, HASH_SIZE},
+  Error, Result,
+};
+
+/// The type of the chunk holding the manifest: ancillary, private and safe
+/// to copy.
+pub const MANIFEST_CHUNK_TYPE: [u8; 4] = *b"maNf";
+
+const VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+  name: String,
+  chunk_type: ChunkType,
+  size: u64,
+  hash: [u8; HASH_SIZE],
+}
+
+impl Entry {
+  /// An entry for `payload` as stored in chunks of type `chunk_type`. Names
+  /// are 1 to 255 bytes without control characters.
+  pub fn new(name: String, chunk_type: ChunkType, payload: &[u8]) -> Result<Self> {
+    Entry::with_hash(
+      name,
+      chunk_type,
+      payload.len() as u64,
+      fragment::payload_hash(payload),
+    )
+  }
+
+  fn with_hash(
+    name: String,
+    chunk_type: ChunkType,
+    size: u64,
+    hash: [u8; HASH_SIZE],
+  ) -> Result<Self> {
+    if name.is_empty() || name.len() > u8::MAX as usize || name.contains(char::is_control) {
+      return Err(Error::InvalidManifest(format!(
+        "'{}' is not a valid message name",
+        name.escape_debug()
+      )));
+    }
+    Ok(Entry {
+      name,
+      chunk_type,
+      size,
+      hash,
+    })
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn chunk_type(&self) -> &ChunkType {
+    &self.chunk_type
+  }
+
+  /// The size of the stored payload, envelope included.
+  pub fn size(&self) -> u64 {
+    self.size
+  }
+
+  /// The hash of the stored payload, see [`fragment::payload_hash`].
+  pub fn hash(&self) -> [u8; HASH_SIZE] {
+    self.hash
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+  entries: Vec<Entry>,
+}
+
+impl TryFrom<&[u8]> for Manifest {
+  type Error = Error;
+
+  /// Parses the data of a `maNf` chunk.
+  fn try_from(data: &[u8]) -> Result<Self> {
+    let truncated = || Error::InvalidManifest("truncated data".to_string());
+    let mut rest = data;
+    let version = take(&mut rest, 1).ok_or_else(truncated)?[0];
+    if version != VERSION {
+      return Err(Error::InvalidManifest(format!(
+        "version {} is not supported",
+        version
+      )));
+    }
+    let count = take(&mut rest, 2).ok_or_else(truncated)?;
+    let count = u16::from_be_bytes([count[0], count[1]]);
+
+    let mut manifest = Manifest::default();
+    for _ in 0..count {
+      let name_length = take(&mut rest, 1).ok_or_else(truncated)?[0];
+      let name = take(&mut rest, name_length as usize).ok_or_else(truncated)?;
+      let name = String::from_utf8(name.to_vec())
+        .map_err(|_| Error::InvalidManifest("name is not valid utf-8".to_string()))?;
+      let chunk_type = take(&mut rest, 4).ok_or_else(truncated)?;
+      let chunk_type = ChunkType::try_from(<[u8; 4]>::try_from(chunk_type).unwrap())?;
+      let size = take(&mut rest, 8).ok_or_else(truncated)?;
+      let size = u64::from_be_bytes(size.try_into().unwrap());
+      let hash = take(&mut rest, HASH_SIZE).ok_or_else(truncated)?;
+      let entry = Entry::with_hash(name, chunk_type, size, hash.try_into().unwrap())?;
+      manifest.insert(entry)?;
+    }
+    if !rest.is_empty() {
+      return Err(Error::InvalidManifest(format!(
+        "{} unexpected trailing bytes",
+        rest.len()
+      )));
+    }
+    Ok(manifest)
+  }
+}
+
+impl Manifest {
+  pub fn entries(&self) -> &[Entry] {
+    &self.entries
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  pub fn get(&self, name: &str) -> Option<&Entry> {
+    self.entries.iter().find(|entry| entry.name == name)
+  }
+
+  /// Adds `entry`, refusing a name that is already taken.
+  pub fn insert(&mut self, entry: Entry) -> Result<()> {
+    if self.get(&entry.name).is_some() {
+      return Err(Error::MessageNameTaken(entry.name));
+    }
+    if self.entries.len() == u16::MAX as usize {
+      return Err(Error::InvalidManifest(format!(
+        "at most {} messages can be named",
+        u16::MAX
+      )));
+    }
+    self.entries.push(entry);
+    Ok(())
+  }
+
+  /// Removes the entry called `name`.
+  pub fn remove(&mut self, name: &str) -> Option<Entry> {
+    let index = self.entries.iter().position(|entry| entry.name == name)?;
+    Some(self.entries.remove(index))
+  }
+
+  /// Removes the first entry naming the payload with `hash` in chunks of
+  /// type `chunk_type`.
+  pub fn remove_message(
+    &mut self,
+    chunk_type: &ChunkType,
+    hash: &[u8; HASH_SIZE],
+  ) -> Option<Entry> {
+    let index = self
+      .entries
+      .iter()
+      .position(|entry| entry.chunk_type == *chunk_type && entry.hash == *hash)?;
+    Some(self.entries.remove(index))
+  }
+
+  pub fn as_bytes(&self) -> Vec<u8> {
+    let mut bytes = vec![VERSION];
+    bytes.extend_from_slice(&(self.entries.len() as u16).to_be_bytes());
+    for entry in &self.entries {
+      bytes.push(entry.name.len() as u8);
+      bytes.extend_from_slice(entry.name.as_bytes());
+      bytes.extend_from_slice(&entry.chunk_type.bytes());
+      bytes.extend_from_slice(&entry.size.to_be_bytes());
+      bytes.extend_from_slice(&entry.hash);
+    }
+    bytes
+  }
+
+  /// The `maNf` chunk to store in the image.
+  pub fn to_chunk(&self) -> Chunk {
+    Chunk::new(
+      ChunkType::try_from(MANIFEST_CHUNK_TYPE).unwrap(),
+      self.as_bytes(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::str::FromStr;
+
+  use super::*;
+
+  fn manifest() -> Manifest {
+    let ru_st = ChunkType::from_str("ruSt").unwrap();
+    let mut manifest = Manifest::default();
+    manifest
+      .insert(Entry::new("notes".into(), ru_st, b"first").unwrap())
+      .unwrap();
+    manifest
+      .insert(Entry::new("todo ✓".into(), ru_st, b"second").unwrap())
+      .unwrap();
+    manifest
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let manifest = manifest();
+    let chunk = manifest.to_chunk();
+    assert_eq!(chunk.chunk_type().bytes(), MANIFEST_CHUNK_TYPE);
+    assert!(chunk.chunk_type().is_valid());
+    assert_eq!(Manifest::try_from(chunk.data()).unwrap(), manifest);
+    assert_eq!(
+      Manifest::try_from(&[1, 0, 0][..]).unwrap(),
+      Manifest::default()
+    );
+  }
+
+  #[test]
+  fn test_lookup_and_remove() {
+    let mut manifest = manifest();
+    let entry = manifest.get("notes").unwrap().clone();
+    assert_eq!(entry.size(), 5);
+    assert_eq!(entry.hash(), fragment::payload_hash(b"first"));
+    assert!(manifest.get("other").is_none());
+
+    let removed = manifest
+      .remove_message(entry.chunk_type(), &entry.hash())
+      .unwrap();
+    assert_eq!(removed, entry);
+    assert_eq!(manifest.remove("todo ✓").unwrap().size(), 6);
+    assert!(manifest.is_empty());
+  }
+
+  #[test]
+  fn test_rejects_bad_names() {
+    let ru_st = ChunkType::from_str("ruSt").unwrap();
+    let mut manifest = manifest();
+    assert!(matches!(
+      manifest.insert(Entry::new("notes".into(), ru_st, b"third").unwrap()),
+      Err(Error::MessageNameTaken(_))
+    ));
+    for name in [String::new(), "a\nb".into(), "x".repeat(256)] {
+      assert!(Entry::new(name, ru_st, b"").is_err());
+    }
+  }
+
+  #[test]
+  fn test_rejects_malformed_data() {
+    let bytes = manifest().as_bytes();
+    for end in [0, 2, 4, 10, bytes.len() - 1] {
+      assert!(matches!(
+        Manifest::try_from(&bytes[..end]),
+        Err(Error::InvalidManifest(_))
+      ));
+    }
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert!(Manifest::try_from(trailing.as_slice()).is_err());
+    let mut version = bytes;
+    version[0] = 2;
+    assert!(Manifest::try_from(version.as_slice()).is_err());
+  }
+}
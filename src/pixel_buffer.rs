@@ -0,0 +1,423 @@
+use crate::{
+  image_header::{ColorType, ImageHeader, InterlaceMethod},
+  png::Png,
+  Error, Result,
+};
+
+/// One pixel as stored, with samples at the image's bit depth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pixel {
+  Grayscale(u16),
+  GrayscaleAlpha(u16, u16),
+  Rgb(u16, u16, u16),
+  Rgba(u16, u16, u16, u16),
+  Indexed(u8),
+}
+
+/// The tRNS chunk: a single transparent color, or per-entry palette alpha.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transparency {
+  Grayscale(u16),
+  Rgb(u16, u16, u16),
+  Palette(Vec<u8>),
+}
+
+impl Transparency {
+  fn parse(data: &[u8], color_type: ColorType) -> Result<Self> {
+    let sample = |i: usize| u16::from_be_bytes([data[i], data[i + 1]]);
+    match (color_type, data.len()) {
+      (ColorType::Grayscale, 2) => Ok(Transparency::Grayscale(sample(0))),
+      (ColorType::Rgb, 6) => Ok(Transparency::Rgb(sample(0), sample(2), sample(4))),
+      (ColorType::Indexed, _) => Ok(Transparency::Palette(data.to_vec())),
+      _ => Err(Error::InvalidImageData(format!(
+        "tRNS chunk of {} bytes is not valid for {} images",
+        data.len(),
+        color_type
+      ))),
+    }
+  }
+
+  /// Serializes the transparency as tRNS chunk data.
+  pub fn to_bytes(&self) -> Vec<u8> {
+    match self {
+      Transparency::Grayscale(gray) => gray.to_be_bytes().to_vec(),
+      Transparency::Rgb(r, g, b) => [r, g, b].iter().flat_map(|s| s.to_be_bytes()).collect(),
+      Transparency::Palette(alpha) => alpha.clone(),
+    }
+  }
+}
+
+/// Decoded, unfiltered and deinterlaced image data in its stored format,
+/// with the palette and transparency needed to interpret it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PixelBuffer {
+  width: u32,
+  height: u32,
+  color_type: ColorType,
+  bit_depth: u8,
+  data: Vec<u8>,
+  palette: Option<Vec<[u8; 3]>>,
+  transparency: Option<Transparency>,
+}
+
+impl TryFrom<&Png> for PixelBuffer {
+  type Error = Error;
+  fn try_from(png: &Png) -> Result<Self> {
+    let header = png.image_header()?;
+    let mut buffer = PixelBuffer::new(
+      header.width,
+      header.height,
+      header.color_type,
+      header.bit_depth,
+      png.decode_scanlines()?,
+    )?;
+
+    if let Some(plte) = png.chunk_by_type("PLTE") {
+      buffer.set_palette(parse_palette(plte.data())?)?;
+    } else if header.color_type == ColorType::Indexed {
+      return Err(Error::ChunkNotFound("PLTE".to_string()));
+    }
+    if let Some(trns) = png.chunk_by_type("tRNS") {
+      buffer.transparency = Some(Transparency::parse(trns.data(), header.color_type)?);
+    }
+    Ok(buffer)
+  }
+}
+
+impl PixelBuffer {
+  /// Wraps raw rows packed as PNG stores them: samples big-endian, pixels
+  /// narrower than a byte packed most significant first, rows padded to a
+  /// whole byte.
+  pub fn new(
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+    bit_depth: u8,
+    data: Vec<u8>,
+  ) -> Result<Self> {
+    let header = ImageHeader {
+      width,
+      height,
+      bit_depth,
+      color_type,
+      compression_method: 0,
+      filter_method: 0,
+      interlace_method: InterlaceMethod::None,
+    };
+    header.validate()?;
+    let expected = header.row_bytes(width).checked_mul(height as usize);
+    if expected != Some(data.len()) {
+      return Err(Error::InvalidImageData(format!(
+        "{}x{} {}-bit {} needs {} bytes, got {}",
+        width,
+        height,
+        bit_depth,
+        color_type,
+        expected.unwrap_or(usize::MAX),
+        data.len()
+      )));
+    }
+
+    Ok(PixelBuffer {
+      width,
+      height,
+      color_type,
+      bit_depth,
+      data,
+      palette: None,
+      transparency: None,
+    })
+  }
+
+  pub fn width(&self) -> u32 {
+    self.width
+  }
+
+  pub fn height(&self) -> u32 {
+    self.height
+  }
+
+  pub fn color_type(&self) -> ColorType {
+    self.color_type
+  }
+
+  pub fn bit_depth(&self) -> u8 {
+    self.bit_depth
+  }
+
+  pub fn data(&self) -> &[u8] {
+    &self.data
+  }
+
+  pub fn data_mut(&mut self) -> &mut [u8] {
+    &mut self.data
+  }
+
+  pub fn into_data(self) -> Vec<u8> {
+    self.data
+  }
+
+  pub fn palette(&self) -> Option<&[[u8; 3]]> {
+    self.palette.as_deref()
+  }
+
+  /// Sets the palette, which must have between 1 and 256 entries.
+  pub fn set_palette(&mut self, palette: Vec<[u8; 3]>) -> Result<()> {
+    if palette.is_empty() || palette.len() > 256 {
+      return Err(Error::InvalidImageData(format!(
+        "palette must have 1 to 256 entries, got {}",
+        palette.len()
+      )));
+    }
+    self.palette = Some(palette);
+    Ok(())
+  }
+
+  pub fn transparency(&self) -> Option<&Transparency> {
+    self.transparency.as_ref()
+  }
+
+  pub fn set_transparency(&mut self, transparency: Option<Transparency>) {
+    self.transparency = transparency;
+  }
+
+  /// Bytes per row, including padding bits in the last byte.
+  pub fn row_bytes(&self) -> usize {
+    (self.width as usize * self.bits_per_pixel()).div_ceil(8)
+  }
+
+  pub fn bits_per_pixel(&self) -> usize {
+    self.color_type.channels() * self.bit_depth as usize
+  }
+
+  pub fn row(&self, y: u32) -> &[u8] {
+    let row_bytes = self.row_bytes();
+    &self.data[y as usize * row_bytes..][..row_bytes]
+  }
+
+  pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+    self
+      .data
+      .chunks_exact(self.row_bytes().max(1))
+      .take(self.height as usize)
+  }
+
+  /// Reads sample `index` of `row`, counting every channel of every pixel.
+  fn sample(&self, row: &[u8], index: usize) -> u16 {
+    match self.bit_depth {
+      16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+      8 => row[index] as u16,
+      bits => {
+        let bits = bits as usize;
+        let per_byte = 8 / bits;
+        let shift = 8 - bits * (index % per_byte + 1);
+        ((row[index / per_byte] >> shift) as u16) & ((1 << bits) - 1)
+      }
+    }
+  }
+
+  /// The pixel at (`x`, `y`). Panics if it is out of bounds.
+  pub fn pixel(&self, x: u32, y: u32) -> Pixel {
+    assert!(x < self.width && y < self.height, "pixel out of bounds");
+    let row = self.row(y);
+    let first = x as usize * self.color_type.channels();
+    let s = |channel: usize| self.sample(row, first + channel);
+    match self.color_type {
+      ColorType::Grayscale => Pixel::Grayscale(s(0)),
+      ColorType::GrayscaleAlpha => Pixel::GrayscaleAlpha(s(0), s(1)),
+      ColorType::Rgb => Pixel::Rgb(s(0), s(1), s(2)),
+      ColorType::Rgba => Pixel::Rgba(s(0), s(1), s(2), s(3)),
+      ColorType::Indexed => Pixel::Indexed(s(0) as u8),
+    }
+  }
+
+  /// Every pixel as 16-bit RGBA, applying the palette and transparency.
+  pub fn to_rgba16(&self) -> Result<Vec<[u16; 4]>> {
+    let max = ((1u32 << self.bit_depth) - 1) as u16;
+    let scale = |value: u16| ((value as u32 * 65535 + max as u32 / 2) / max as u32) as u16;
+    let opaque_unless = |transparent: bool| if transparent { 0 } else { 65535 };
+
+    let mut output = Vec::with_capacity(self.width as usize * self.height as usize);
+    for y in 0..self.height {
+      for x in 0..self.width {
+        let rgba = match (self.pixel(x, y), &self.transparency) {
+          (Pixel::Grayscale(v), transparency) => {
+            let alpha = opaque_unless(transparency == &Some(Transparency::Grayscale(v)));
+            [scale(v), scale(v), scale(v), alpha]
+          }
+          (Pixel::GrayscaleAlpha(v, a), _) => [scale(v), scale(v), scale(v), scale(a)],
+          (Pixel::Rgb(r, g, b), transparency) => {
+            let alpha = opaque_unless(transparency == &Some(Transparency::Rgb(r, g, b)));
+            [scale(r), scale(g), scale(b), alpha]
+          }
+          (Pixel::Rgba(r, g, b, a), _) => [scale(r), scale(g), scale(b), scale(a)],
+          (Pixel::Indexed(index), transparency) => {
+            let palette = self
+              .palette
+              .as_ref()
+              .ok_or_else(|| Error::ChunkNotFound("PLTE".to_string()))?;
+            let [r, g, b] = *palette.get(index as usize).ok_or_else(|| {
+              Error::InvalidImageData(format!(
+                "palette index {} at ({}, {}) is out of range",
+                index, x, y
+              ))
+            })?;
+            let alpha = match transparency {
+              Some(Transparency::Palette(alpha)) => *alpha.get(index as usize).unwrap_or(&255),
+              _ => 255,
+            };
+            [r, g, b, alpha].map(|v| v as u16 * 257)
+          }
+        };
+        output.push(rgba);
+      }
+    }
+    Ok(output)
+  }
+
+  /// Every pixel as 8-bit RGBA, applying the palette and transparency.
+  pub fn to_rgba8(&self) -> Result<Vec<[u8; 4]>> {
+    Ok(
+      self
+        .to_rgba16()?
+        .into_iter()
+        .map(|pixel| pixel.map(|v| ((v as u32 * 255 + 32767) / 65535) as u8))
+        .collect(),
+    )
+  }
+}
+
+fn parse_palette(data: &[u8]) -> Result<Vec<[u8; 3]>> {
+  if !data.len().is_multiple_of(3) {
+    return Err(Error::InvalidImageData(format!(
+      "PLTE length {} is not a multiple of 3",
+      data.len()
+    )));
+  }
+  Ok(
+    data
+      .chunks_exact(3)
+      .map(|entry| [entry[0], entry[1], entry[2]])
+      .collect(),
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{chunk::Chunk, chunk_type::ChunkType};
+  use std::str::FromStr;
+
+  #[test]
+  fn test_from_png() {
+    let png = Png::try_from(&RGBA_PNG_FILE[..]).unwrap();
+    let buffer = PixelBuffer::try_from(&png).unwrap();
+    assert_eq!((buffer.width(), buffer.height()), (2, 2));
+    assert_eq!(buffer.pixel(0, 0), Pixel::Rgba(255, 0, 0, 255));
+    assert_eq!(buffer.pixel(1, 1), Pixel::Rgba(255, 255, 255, 255));
+    assert_eq!(
+      buffer.to_rgba8().unwrap(),
+      [
+        [255, 0, 0, 255],
+        [0, 255, 0, 255],
+        [0, 0, 255, 255],
+        [255, 255, 255, 255]
+      ]
+    );
+  }
+
+  #[test]
+  fn test_low_bit_depth_grayscale() {
+    // 4x1, 2-bit: 0, 1, 2, 3
+    let buffer = PixelBuffer::new(4, 1, ColorType::Grayscale, 2, vec![0b00_01_10_11]).unwrap();
+    let pixels: Vec<Pixel> = (0..4).map(|x| buffer.pixel(x, 0)).collect();
+    assert_eq!(pixels, [0, 1, 2, 3].map(Pixel::Grayscale));
+    assert_eq!(
+      buffer.to_rgba8().unwrap(),
+      [
+        [0, 0, 0, 255],
+        [85, 85, 85, 255],
+        [170, 170, 170, 255],
+        [255, 255, 255, 255]
+      ]
+    );
+  }
+
+  #[test]
+  fn test_sixteen_bit_and_transparency() {
+    let data = [
+      0x12, 0x34, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x00, 0x03,
+    ]
+    .to_vec();
+    let mut buffer = PixelBuffer::new(2, 1, ColorType::Rgb, 16, data).unwrap();
+    assert_eq!(buffer.pixel(0, 0), Pixel::Rgb(0x1234, 0xFFFF, 0));
+    buffer.set_transparency(Some(Transparency::Rgb(1, 2, 3)));
+
+    let rgba = buffer.to_rgba16().unwrap();
+    assert_eq!(rgba, [[0x1234, 0xFFFF, 0, 65535], [1, 2, 3, 0]]);
+    assert_eq!(buffer.to_rgba8().unwrap()[0], [0x12, 255, 0, 255]);
+  }
+
+  #[test]
+  fn test_palette() {
+    let mut buffer = PixelBuffer::new(3, 1, ColorType::Indexed, 4, vec![0x01, 0x20]).unwrap();
+    assert!(matches!(buffer.to_rgba8(), Err(Error::ChunkNotFound(_))));
+
+    buffer
+      .set_palette(vec![[10, 20, 30], [40, 50, 60], [70, 80, 90]])
+      .unwrap();
+    buffer.set_transparency(Some(Transparency::Palette(vec![0, 128])));
+    assert_eq!(
+      buffer.to_rgba8().unwrap(),
+      [[10, 20, 30, 0], [40, 50, 60, 128], [70, 80, 90, 255]]
+    );
+
+    buffer.set_palette(vec![[0, 0, 0]]).unwrap();
+    assert!(matches!(buffer.to_rgba8(), Err(Error::InvalidImageData(_))));
+  }
+
+  #[test]
+  fn test_palette_from_png() {
+    let mut png = Png::from_chunks(vec![]);
+    let header = ImageHeader {
+      width: 2,
+      height: 1,
+      bit_depth: 1,
+      color_type: ColorType::Indexed,
+      compression_method: 0,
+      filter_method: 0,
+      interlace_method: InterlaceMethod::None,
+    };
+    png.append_chunk(header.to_chunk());
+    png.append_chunk(Chunk::new(
+      ChunkType::from_str("PLTE").unwrap(),
+      vec![1, 2, 3, 4, 5, 6],
+    ));
+    png.append_chunk(Chunk::new(ChunkType::from_str("tRNS").unwrap(), vec![7]));
+    png
+      .encode_scanlines(
+        &[0b0100_0000],
+        crate::filter::FilterStrategy::MinSum,
+        crate::deflate::CompressionLevel::Fast,
+      )
+      .unwrap();
+
+    let buffer = PixelBuffer::try_from(&png).unwrap();
+    assert_eq!(buffer.palette().unwrap().len(), 2);
+    assert_eq!(buffer.to_rgba8().unwrap(), [[1, 2, 3, 7], [4, 5, 6, 255]]);
+  }
+
+  #[test]
+  fn test_wrong_size() {
+    assert!(PixelBuffer::new(3, 3, ColorType::Rgb, 8, vec![0; 26]).is_err());
+    assert!(PixelBuffer::new(3, 3, ColorType::Rgb, 4, vec![0; 27]).is_err());
+  }
+
+  // 2x2 RGBA: red, green / blue, white
+  const RGBA_PNG_FILE: [u8; 75] = [
+    137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 2, 0, 0, 0, 2, 8, 6, 0,
+    0, 0, 114, 182, 13, 36, 0, 0, 0, 18, 73, 68, 65, 84, 120, 218, 99, 248, 207, 192, 240, 31, 12,
+    129, 52, 24, 0, 0, 73, 200, 9, 247, 3, 217, 100, 241, 0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96,
+    130,
+  ];
+}
@@ -1 +1,452 @@
+use std::{fmt::Display, str::FromStr};
 
+use crate::{
+  chunk::Chunk,
+  chunk_ref::ChunkRefs,
+  chunk_type::ChunkType,
+  deflate::{self, CompressionLevel},
+  filter::{self, FilterStrategy},
+  image_header::ImageHeader,
+  inflate, Error,
+};
+
+/// IDAT chunk size used when re-emitting image data, as libpng does.
+pub const DEFAULT_IDAT_SIZE: usize = 8192;
+
+#[derive(Debug)]
+pub struct Png {
+  chunks: Vec<Chunk>,
+}
+
+impl TryFrom<&[u8]> for Png {
+  type Error = Error;
+  fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+    let chunks = ChunkRefs::new(value)?
+      .map(|chunk| chunk.map(Chunk::from))
+      .collect::<Result<_, _>>()?;
+
+    Ok(Png { chunks })
+  }
+}
+
+impl Display for Png {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "Png {{ {} chunks }}", self.chunks.len())?;
+    for chunk in &self.chunks {
+      writeln!(f, "  {}", chunk)?;
+    }
+    Ok(())
+  }
+}
+
+impl Png {
+  pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+  pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
+    Png { chunks }
+  }
+
+  pub fn append_chunk(&mut self, chunk: Chunk) {
+    self.chunks.push(chunk);
+  }
+
+  pub fn remove_first_chunk(&mut self, chunk_type: &str) -> crate::Result<Chunk> {
+    let index = self
+      .chunks
+      .iter()
+      .position(|chunk| chunk.chunk_type().to_string() == chunk_type)
+      .ok_or_else(|| Error::ChunkNotFound(chunk_type.to_string()))?;
+    Ok(self.chunks.remove(index))
+  }
+
+  pub fn header(&self) -> &[u8; 8] {
+    &Png::STANDARD_HEADER
+  }
+
+  pub fn chunks(&self) -> &[Chunk] {
+    &self.chunks
+  }
+
+  pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
+    self
+      .chunks
+      .iter()
+      .find(|chunk| chunk.chunk_type().to_string() == chunk_type)
+  }
+
+  /// Decodes the IHDR chunk, which must be the first chunk in the file.
+  pub fn image_header(&self) -> crate::Result<ImageHeader> {
+    let first = self
+      .chunks
+      .first()
+      .ok_or_else(|| Error::ChunkNotFound("IHDR".to_string()))?;
+    ImageHeader::try_from(first)
+  }
+
+  /// The data of all IDAT chunks, concatenated into one zlib stream.
+  pub fn image_data(&self) -> Vec<u8> {
+    self
+      .chunks
+      .iter()
+      .filter(|chunk| chunk.chunk_type().bytes() == *b"IDAT")
+      .flat_map(|chunk| chunk.data().iter().copied())
+      .collect()
+  }
+
+  /// Decompresses the IDAT stream, refusing to produce more than `limit`
+  /// bytes.
+  pub fn decompress_image_data(&self, limit: usize) -> crate::Result<Vec<u8>> {
+    inflate::zlib_decompress(&self.image_data(), limit)
+  }
+
+  /// Compresses `data` and replaces all IDAT chunks with new ones holding at
+  /// most `max_chunk_size` bytes each. The new chunks go where the first IDAT
+  /// was, or before IEND if there was none.
+  pub fn set_image_data(&mut self, data: &[u8], level: CompressionLevel, max_chunk_size: usize) {
+    let is_idat = |chunk: &Chunk| chunk.chunk_type().bytes() == *b"IDAT";
+    let position = self
+      .chunks
+      .iter()
+      .position(is_idat)
+      .or_else(|| {
+        self
+          .chunks
+          .iter()
+          .position(|chunk| chunk.chunk_type().bytes() == *b"IEND")
+      })
+      .unwrap_or(self.chunks.len());
+    let position = position
+      - self.chunks[..position]
+        .iter()
+        .filter(|c| is_idat(c))
+        .count();
+    self.chunks.retain(|chunk| !is_idat(chunk));
+
+    let idat = ChunkType::from_str("IDAT").unwrap();
+    let compressed = deflate::zlib_compress(data, level);
+    let max_chunk_size = max_chunk_size.clamp(1, Chunk::MAX_LENGTH);
+    let chunks = compressed
+      .chunks(max_chunk_size)
+      .map(|part| Chunk::new(idat, part.to_vec()));
+    self.chunks.splice(position..position, chunks);
+  }
+
+  /// Decompresses and unfilters the image data into raw rows, packed as
+  /// described by IHDR. Interlaced images are merged into full resolution.
+  /// Decompression stops at the size IHDR implies.
+  pub fn decode_scanlines(&self) -> crate::Result<Vec<u8>> {
+    let header = self.image_header()?;
+    let filtered_size = header
+      .filtered_size()
+      .ok_or_else(|| Error::InvalidImageData("image is too large".to_string()))?;
+    let filtered = self.decompress_image_data(filtered_size)?;
+    filter::unfilter_image(&filtered, &header)
+  }
+
+  /// Filters raw rows packed as described by IHDR, interlacing them if IHDR
+  /// says so, and stores them as the new image data.
+  pub fn encode_scanlines(
+    &mut self,
+    raw: &[u8],
+    strategy: FilterStrategy,
+    level: CompressionLevel,
+  ) -> crate::Result<()> {
+    let filtered = filter::filter_image(raw, &self.image_header()?, strategy)?;
+    self.set_image_data(&filtered, level, DEFAULT_IDAT_SIZE);
+    Ok(())
+  }
+
+  pub fn as_bytes(&self) -> Vec<u8> {
+    self
+      .header()
+      .iter()
+      .copied()
+      .chain(self.chunks.iter().flat_map(|chunk| chunk.as_bytes()))
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::chunk::Chunk;
+  use crate::chunk_type::ChunkType;
+  use std::convert::TryFrom;
+  use std::str::FromStr;
+
+  fn testing_chunks() -> Vec<Chunk> {
+    vec![
+      chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+      chunk_from_strings("miDl", "I am another chunk").unwrap(),
+      chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+    ]
+  }
+
+  fn testing_png() -> Png {
+    let chunks = testing_chunks();
+    Png::from_chunks(chunks)
+  }
+
+  fn chunk_from_strings(chunk_type: &str, data: &str) -> crate::Result<Chunk> {
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    let data: Vec<u8> = data.bytes().collect();
+
+    Ok(Chunk::new(chunk_type, data))
+  }
+
+  #[test]
+  fn test_from_chunks() {
+    let chunks = testing_chunks();
+    let png = Png::from_chunks(chunks);
+
+    assert_eq!(png.chunks().len(), 3);
+  }
+
+  #[test]
+  fn test_valid_from_bytes() {
+    let chunk_bytes: Vec<u8> = testing_chunks()
+      .into_iter()
+      .flat_map(|chunk| chunk.as_bytes())
+      .collect();
+
+    let bytes: Vec<u8> = Png::STANDARD_HEADER
+      .iter()
+      .chain(chunk_bytes.iter())
+      .copied()
+      .collect();
+
+    let png = Png::try_from(bytes.as_ref());
+
+    assert!(png.is_ok());
+  }
+
+  #[test]
+  fn test_invalid_header() {
+    let chunk_bytes: Vec<u8> = testing_chunks()
+      .into_iter()
+      .flat_map(|chunk| chunk.as_bytes())
+      .collect();
+
+    let bytes: Vec<u8> = [13, 80, 78, 71, 13, 10, 26, 10]
+      .iter()
+      .chain(chunk_bytes.iter())
+      .copied()
+      .collect();
+
+    let png = Png::try_from(bytes.as_ref());
+
+    assert!(matches!(png, Err(Error::InvalidSignature)));
+  }
+
+  #[test]
+  fn test_invalid_chunk() {
+    let mut chunk_bytes: Vec<u8> = testing_chunks()
+      .into_iter()
+      .flat_map(|chunk| chunk.as_bytes())
+      .collect();
+
+    #[rustfmt::skip]
+    let mut bad_chunk = vec![
+      0, 0, 0, 5,         // length
+      32, 117, 83, 116,   // Chunk Type (bad)
+      65, 64, 65, 66, 67, // Data
+      1, 2, 3, 4, 5       // CRC (bad)
+    ];
+
+    chunk_bytes.append(&mut bad_chunk);
+
+    let png = Png::try_from(chunk_bytes.as_ref());
+
+    assert!(png.is_err());
+  }
+
+  #[test]
+  fn test_truncated_png() {
+    let bytes = testing_png().as_bytes();
+
+    assert!(matches!(
+      Png::try_from(&bytes[..bytes.len() - 1]),
+      Err(Error::Truncated { offset: 70, .. })
+    ));
+    assert!(Png::try_from(&bytes[..10]).is_err());
+    assert!(Png::try_from(&bytes[..4]).is_err());
+  }
+
+  #[test]
+  fn test_crc_mismatch_offset() {
+    let mut bytes = testing_png().as_bytes();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+
+    assert!(matches!(
+      Png::try_from(bytes.as_ref()),
+      Err(Error::CrcMismatch { offset: 70, .. })
+    ));
+  }
+
+  #[test]
+  fn test_list_chunks() {
+    let png = testing_png();
+    let chunks = png.chunks();
+    assert_eq!(chunks.len(), 3);
+  }
+
+  #[test]
+  fn test_chunk_by_type() {
+    let png = testing_png();
+    let chunk = png.chunk_by_type("FrSt").unwrap();
+    assert_eq!(&chunk.chunk_type().to_string(), "FrSt");
+    assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
+  }
+
+  #[test]
+  fn test_append_chunk() {
+    let mut png = testing_png();
+    png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+    let chunk = png.chunk_by_type("TeSt").unwrap();
+    assert_eq!(&chunk.chunk_type().to_string(), "TeSt");
+    assert_eq!(&chunk.data_as_string().unwrap(), "Message");
+  }
+
+  #[test]
+  fn test_remove_chunk() {
+    let mut png = testing_png();
+    png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+    png.remove_first_chunk("TeSt").unwrap();
+    let chunk = png.chunk_by_type("TeSt");
+    assert!(chunk.is_none());
+  }
+
+  #[test]
+  fn test_remove_missing_chunk() {
+    let mut png = testing_png();
+    assert!(matches!(
+      png.remove_first_chunk("TeSt"),
+      Err(Error::ChunkNotFound(_))
+    ));
+    assert_eq!(png.chunks().len(), 3);
+  }
+
+  #[test]
+  fn test_png_from_image_file() {
+    let png = Png::try_from(&PNG_FILE[..]);
+    assert!(png.is_ok());
+  }
+
+  #[test]
+  fn test_as_bytes() {
+    let png = Png::try_from(&PNG_FILE[..]).unwrap();
+    let actual = png.as_bytes();
+    let expected: Vec<u8> = PNG_FILE.to_vec();
+    assert_eq!(actual, expected);
+  }
+
+  #[test]
+  fn test_decompress_image_data() {
+    let png = Png::try_from(&PNG_FILE[..]).unwrap();
+    let data = png
+      .decompress_image_data(inflate::DEFAULT_OUTPUT_LIMIT)
+      .unwrap();
+    assert_eq!(
+      data,
+      [0, 255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 0, 255, 255, 255, 255, 255, 255]
+    );
+    assert!(png.decompress_image_data(17).is_err());
+  }
+
+  #[test]
+  fn test_set_image_data() {
+    let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+    let data = png
+      .decompress_image_data(inflate::DEFAULT_OUTPUT_LIMIT)
+      .unwrap();
+    png.set_image_data(&data, CompressionLevel::Stored, 10);
+
+    let types: Vec<String> = png
+      .chunks()
+      .iter()
+      .map(|chunk| chunk.chunk_type().to_string())
+      .collect();
+    assert_eq!(types, ["IHDR", "IDAT", "IDAT", "IDAT", "IEND"]);
+    assert!(png.chunks()[1..4].iter().all(|chunk| chunk.length() <= 10));
+
+    let reparsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+    assert_eq!(
+      reparsed
+        .decompress_image_data(inflate::DEFAULT_OUTPUT_LIMIT)
+        .unwrap(),
+      data
+    );
+  }
+
+  #[test]
+  fn test_scanlines_round_trip() {
+    let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+    let raw = png.decode_scanlines().unwrap();
+    assert_eq!(
+      raw,
+      [255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255, 255, 255, 255]
+    );
+
+    png
+      .encode_scanlines(&raw, FilterStrategy::MinSum, CompressionLevel::Best)
+      .unwrap();
+    let reparsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+    assert_eq!(reparsed.decode_scanlines().unwrap(), raw);
+
+    assert!(matches!(
+      png.encode_scanlines(&raw[1..], FilterStrategy::MinSum, CompressionLevel::Best),
+      Err(Error::InvalidImageData(_))
+    ));
+  }
+
+  #[test]
+  fn test_decode_interlaced() {
+    let mut png = Png::try_from(&INTERLACED_PNG_FILE[..]).unwrap();
+    let raw = png.decode_scanlines().unwrap();
+    let expected: Vec<u8> = (0..3)
+      .flat_map(|y| (0..5).map(move |x| y * 16 + x))
+      .collect();
+    assert_eq!(raw, expected);
+
+    png
+      .encode_scanlines(&raw, FilterStrategy::MinSum, CompressionLevel::Fast)
+      .unwrap();
+    let reparsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+    assert_eq!(reparsed.decode_scanlines().unwrap(), expected);
+  }
+
+  #[test]
+  fn test_png_trait_impls() {
+    let chunk_bytes: Vec<u8> = testing_chunks()
+      .into_iter()
+      .flat_map(|chunk| chunk.as_bytes())
+      .collect();
+
+    let bytes: Vec<u8> = Png::STANDARD_HEADER
+      .iter()
+      .chain(chunk_bytes.iter())
+      .copied()
+      .collect();
+
+    let png: Png = TryFrom::try_from(bytes.as_ref()).unwrap();
+
+    let _png_string = format!("{}", png);
+  }
+
+  // 2x2 RGBA image written by a standard encoder
+  const PNG_FILE: [u8; 75] = [
+    137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 2, 0, 0, 0, 2, 8, 6, 0,
+    0, 0, 114, 182, 13, 36, 0, 0, 0, 18, 73, 68, 65, 84, 120, 218, 99, 248, 207, 192, 240, 31, 12,
+    129, 52, 24, 0, 0, 73, 200, 9, 247, 3, 217, 100, 241, 0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96,
+    130,
+  ];
+
+  // 5x3 8-bit grayscale, Adam7 interlaced, pixel (x, y) = y * 16 + x
+  const INTERLACED_PNG_FILE: [u8; 90] = [
+    137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 5, 0, 0, 0, 3, 8, 0, 0,
+    0, 1, 9, 90, 170, 178, 0, 0, 0, 33, 73, 68, 65, 84, 120, 1, 1, 22, 0, 233, 255, 0, 0, 0, 4, 0,
+    2, 0, 32, 34, 36, 0, 1, 3, 0, 33, 35, 0, 16, 17, 18, 19, 20, 9, 62, 1, 15, 219, 128, 32, 72, 0,
+    0, 0, 0, 73, 69, 78, 68, 174, 66, 96, 130,
+  ];
+}
@@ -0,0 +1,415 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, crc::Crc32, png::Png, Error, Result};
+
+/// Size of the buffer used when streaming chunk data.
+const COPY_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Length and type of a chunk, read without touching its data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkHeader {
+  /// Position of the chunk's length field from the start of the file.
+  pub offset: u64,
+  pub length: u32,
+  pub chunk_type: ChunkType,
+}
+
+/// Reads a PNG one chunk at a time from any [`Read`].
+///
+/// Iterating yields whole [`Chunk`]s; [`ChunkReader::headers`] and
+/// [`ChunkReader::headers_seek`] yield only [`ChunkHeader`]s and never hold
+/// more than a small buffer of chunk data in memory. Iteration stops at the
+/// end of the input or after the first error.
+#[derive(Debug)]
+pub struct ChunkReader<R> {
+  reader: R,
+  offset: u64,
+  current: Option<ChunkHeader>,
+  done: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+  /// Reads and checks the PNG signature.
+  pub fn new(mut reader: R) -> Result<Self> {
+    let mut signature = [0u8; 8];
+    let read = read_full(&mut reader, &mut signature)?;
+    if read < signature.len() && signature[..read] == Png::STANDARD_HEADER[..read] {
+      return Err(Error::Truncated {
+        offset: 0,
+        needed: signature.len(),
+        available: read,
+      });
+    }
+    if signature != Png::STANDARD_HEADER {
+      return Err(Error::InvalidSignature);
+    }
+
+    Ok(ChunkReader {
+      reader,
+      offset: signature.len() as u64,
+      current: None,
+      done: false,
+    })
+  }
+
+  /// Reads the next chunk's length and type, skipping (and verifying) the
+  /// data of the current chunk if it has not been read. Returns `None` at
+  /// the end of the input.
+  pub fn next_header(&mut self) -> Result<Option<ChunkHeader>> {
+    if self.current.is_some() {
+      self.skip_data()?;
+    }
+
+    let mut header = [0u8; 8];
+    let read = read_full(&mut self.reader, &mut header)?;
+    if read == 0 {
+      return Ok(None);
+    }
+    if read < header.len() {
+      return Err(Error::Truncated {
+        offset: self.offset as usize,
+        needed: header.len(),
+        available: read,
+      });
+    }
+
+    let header = ChunkHeader {
+      offset: self.offset,
+      length: u32::from_be_bytes([header[0], header[1], header[2], header[3]]),
+      chunk_type: ChunkType::try_from([header[4], header[5], header[6], header[7]])?,
+    };
+    self.offset += 8;
+    self.current = Some(header.clone());
+    Ok(Some(header))
+  }
+
+  /// Reads the data of the current chunk and verifies its CRC.
+  pub fn read_data(&mut self) -> Result<Vec<u8>> {
+    let header = self.take_current()?;
+    let mut data = Vec::new();
+    (&mut self.reader)
+      .take(header.length as u64)
+      .read_to_end(&mut data)?;
+    if data.len() < header.length as usize {
+      return Err(truncated(&header, data.len()));
+    }
+
+    let mut crc = Crc32::new();
+    crc.update(&header.chunk_type.bytes());
+    crc.update(&data);
+    self.finish_chunk(&header, crc)?;
+    Ok(data)
+  }
+
+  /// Discards the data of the current chunk, verifying its CRC as it goes.
+  pub fn skip_data(&mut self) -> Result<()> {
+    self.copy_data(&mut io::sink()).map(|_| ())
+  }
+
+  /// Streams the data of the current chunk into `writer`, verifying its CRC
+  /// as it goes, and returns the CRC.
+  pub fn copy_data<W: Write>(&mut self, writer: &mut W) -> Result<u32> {
+    let header = self.take_current()?;
+    let mut crc = Crc32::new();
+    crc.update(&header.chunk_type.bytes());
+
+    let mut buffer = [0u8; COPY_BUFFER_SIZE];
+    let mut remaining = header.length as usize;
+    while remaining > 0 {
+      let want = remaining.min(buffer.len());
+      let read = read_full(&mut self.reader, &mut buffer[..want])?;
+      crc.update(&buffer[..read]);
+      writer.write_all(&buffer[..read])?;
+      if read < want {
+        return Err(truncated(
+          &header,
+          header.length as usize - remaining + read,
+        ));
+      }
+      remaining -= read;
+    }
+    self.finish_chunk(&header, crc)?;
+    Ok(crc.finish())
+  }
+
+  /// Iterates over chunk headers, reading through and verifying each
+  /// chunk's data.
+  pub fn headers(&mut self) -> Headers<'_, R> {
+    Headers {
+      reader: self,
+      skip: ChunkReader::skip_data,
+    }
+  }
+
+  fn take_current(&mut self) -> Result<ChunkHeader> {
+    self.current.take().ok_or(Error::NoCurrentChunk)
+  }
+
+  /// Reads the stored CRC and compares it against `crc`.
+  fn finish_chunk(&mut self, header: &ChunkHeader, crc: Crc32) -> Result<()> {
+    let mut stored = [0u8; 4];
+    let read = read_full(&mut self.reader, &mut stored)?;
+    if read < stored.len() {
+      return Err(truncated(header, header.length as usize + read));
+    }
+    self.offset += header.length as u64 + 4;
+
+    let found = u32::from_be_bytes(stored);
+    if crc.finish() != found {
+      return Err(Error::CrcMismatch {
+        offset: header.offset as usize,
+        expected: crc.finish(),
+        found,
+      });
+    }
+    Ok(())
+  }
+
+  /// Runs `step` unless a previous step failed, and stops iteration after
+  /// the end of the input or an error.
+  fn advance<T>(&mut self, step: impl FnOnce(&mut Self) -> Result<Option<T>>) -> Option<Result<T>> {
+    if self.done {
+      return None;
+    }
+    let item = step(self).transpose();
+    if !matches!(item, Some(Ok(_))) {
+      self.done = true;
+    }
+    item
+  }
+
+  pub fn into_inner(self) -> R {
+    self.reader
+  }
+}
+
+impl<R: Read + Seek> ChunkReader<R> {
+  /// Seeks past the data and CRC of the current chunk without reading or
+  /// verifying them.
+  pub fn seek_past_data(&mut self) -> Result<()> {
+    let header = self.take_current()?;
+    let skip = header.length as u64 + 4;
+    self.reader.seek(SeekFrom::Current(skip as i64))?;
+    self.offset += skip;
+    Ok(())
+  }
+
+  /// Iterates over chunk headers, seeking past chunk data. CRCs are not
+  /// checked, and a file truncated inside the last chunk is not detected.
+  pub fn headers_seek(&mut self) -> Headers<'_, R> {
+    Headers {
+      reader: self,
+      skip: ChunkReader::seek_past_data,
+    }
+  }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+  type Item = Result<Chunk>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.advance(|reader| {
+      let Some(header) = reader.next_header()? else {
+        return Ok(None);
+      };
+      let data = reader.read_data()?;
+      Ok(Some(Chunk::try_new(header.chunk_type, data)?))
+    })
+  }
+}
+
+/// Iterator over chunk headers returned by [`ChunkReader::headers`] and
+/// [`ChunkReader::headers_seek`].
+pub struct Headers<'a, R> {
+  reader: &'a mut ChunkReader<R>,
+  skip: fn(&mut ChunkReader<R>) -> Result<()>,
+}
+
+impl<R: Read> Iterator for Headers<'_, R> {
+  type Item = Result<ChunkHeader>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let skip = self.skip;
+    self.reader.advance(|reader| {
+      if reader.current.is_some() {
+        skip(reader)?;
+      }
+      reader.next_header()
+    })
+  }
+}
+
+fn truncated(header: &ChunkHeader, data_read: usize) -> Error {
+  Error::Truncated {
+    offset: header.offset as usize,
+    needed: header.length as usize + 12,
+    available: data_read + 8,
+  }
+}
+
+/// Like `read_exact`, but reports how many bytes were read before EOF
+/// instead of failing.
+fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+  let mut read = 0;
+  while read < buffer.len() {
+    match reader.read(&mut buffer[read..]) {
+      Ok(0) => break,
+      Ok(n) => read += n,
+      Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+      Err(e) => return Err(e),
+    }
+  }
+  Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Cursor;
+  use std::str::FromStr;
+
+  fn testing_png() -> Png {
+    Png::from_chunks(vec![
+      Chunk::new(
+        ChunkType::from_str("FrSt").unwrap(),
+        b"I am the first chunk".to_vec(),
+      ),
+      Chunk::new(ChunkType::from_str("miDl").unwrap(), vec![7; 20_000]),
+      Chunk::new(
+        ChunkType::from_str("LASt").unwrap(),
+        b"I am the last chunk".to_vec(),
+      ),
+    ])
+  }
+
+  /// Hands out at most three bytes per read call.
+  struct Trickle<R>(R);
+
+  impl<R: Read> Read for Trickle<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+      let len = buf.len().min(3);
+      self.0.read(&mut buf[..len])
+    }
+  }
+
+  #[test]
+  fn test_read_chunks() {
+    let png = testing_png();
+    let bytes = png.as_bytes();
+    let chunks: Vec<Chunk> = ChunkReader::new(Trickle(bytes.as_slice()))
+      .unwrap()
+      .collect::<Result<_>>()
+      .unwrap();
+
+    assert_eq!(chunks.len(), 3);
+    for (read, expected) in chunks.iter().zip(png.chunks()) {
+      assert_eq!(read.as_bytes(), expected.as_bytes());
+    }
+  }
+
+  #[test]
+  fn test_headers() {
+    let bytes = testing_png().as_bytes();
+    let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+    let headers: Vec<ChunkHeader> = reader.headers().collect::<Result<_>>().unwrap();
+
+    let summary: Vec<(u64, u32, String)> = headers
+      .iter()
+      .map(|h| (h.offset, h.length, h.chunk_type.to_string()))
+      .collect();
+    assert_eq!(
+      summary,
+      vec![
+        (8, 20, "FrSt".to_string()),
+        (40, 20_000, "miDl".to_string()),
+        (20_052, 19, "LASt".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_headers_seek() {
+    let bytes = testing_png().as_bytes();
+    let mut reader = ChunkReader::new(Cursor::new(bytes)).unwrap();
+    let types: Vec<String> = reader
+      .headers_seek()
+      .map(|h| h.unwrap().chunk_type.to_string())
+      .collect();
+
+    assert_eq!(types, vec!["FrSt", "miDl", "LASt"]);
+  }
+
+  #[test]
+  fn test_find_one_chunk() {
+    let bytes = testing_png().as_bytes();
+    let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+    while let Some(header) = reader.next_header().unwrap() {
+      if header.chunk_type.to_string() == "LASt" {
+        assert_eq!(reader.read_data().unwrap(), b"I am the last chunk");
+        return;
+      }
+    }
+    panic!("LASt chunk not found");
+  }
+
+  #[test]
+  fn test_crc_mismatch_in_skipped_data() {
+    let mut bytes = testing_png().as_bytes();
+    bytes[100] ^= 1;
+    let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+    let results: Vec<Result<ChunkHeader>> = reader.headers().collect();
+
+    assert_eq!(results.len(), 3);
+    assert!(matches!(
+      results[2],
+      Err(Error::CrcMismatch { offset: 40, .. })
+    ));
+  }
+
+  #[test]
+  fn test_truncated_input() {
+    let bytes = testing_png().as_bytes();
+    let truncated = &bytes[..bytes.len() - 2];
+    let results: Vec<Result<Chunk>> = ChunkReader::new(truncated).unwrap().collect();
+
+    assert_eq!(results.len(), 3);
+    assert!(matches!(
+      results[2],
+      Err(Error::Truncated { offset: 20_052, .. })
+    ));
+  }
+
+  #[test]
+  fn test_data_needs_a_header() {
+    let bytes = testing_png().as_bytes();
+    let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+    assert!(matches!(reader.read_data(), Err(Error::NoCurrentChunk)));
+    reader.next_header().unwrap();
+    reader.skip_data().unwrap();
+    assert!(matches!(
+      reader.copy_data(&mut io::sink()),
+      Err(Error::NoCurrentChunk)
+    ));
+    assert_eq!(
+      reader
+        .next_header()
+        .unwrap()
+        .unwrap()
+        .chunk_type
+        .to_string(),
+      "miDl"
+    );
+  }
+
+  #[test]
+  fn test_invalid_signature() {
+    assert!(matches!(
+      ChunkReader::new(&b"GIF89a\0\0rest"[..]),
+      Err(Error::InvalidSignature)
+    ));
+    assert!(matches!(
+      ChunkReader::new(&Png::STANDARD_HEADER[..5]),
+      Err(Error::Truncated { available: 5, .. })
+    ));
+  }
+}
@@ -0,0 +1,162 @@
+//! Ed25519 signatures over hidden messages.
+//!
+//! A signature lives in its own `siGn` chunk placed right after the chunk it
+//! signs, so the message chunk itself stays readable by anything that does
+//! not know about signatures. The chunk data is laid out as:
+//!
+//! | bytes | field                          |
+//! |-------|--------------------------------|
+//! | 4     | type of the signed chunk       |
+//! | 32    | signer's Ed25519 public key    |
+//! | 64    | Ed25519 signature              |
+//!
+//! The signature covers a domain label, the signed chunk's type and its
+//! data exactly as stored, so it also vouches for an encrypted payload
+//! without needing the key to open it.
+
+use crate::{
+  chunk::Chunk,
+  chunk_type::ChunkType,
+  keys::{SigningKey, VerifyingKey},
+  Error, Result,
+};
+
+/// The type of the chunk holding a signature: ancillary, private and safe
+/// to copy.
+pub const SIGNATURE_CHUNK_TYPE: [u8; 4] = *b"siGn";
+
+const DOMAIN: &[u8] = b"pngme-signature-v1";
+const SIGNATURE_SIZE: usize = 64;
+const DATA_SIZE: usize = 4 + 32 + SIGNATURE_SIZE;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+  chunk_type: ChunkType,
+  signer: VerifyingKey,
+  bytes: [u8; SIGNATURE_SIZE],
+}
+
+impl TryFrom<&[u8]> for Signature {
+  type Error = Error;
+
+  /// Parses the data of a `siGn` chunk.
+  fn try_from(data: &[u8]) -> Result<Self> {
+    if data.len() != DATA_SIZE {
+      return Err(Error::InvalidSignatureChunk(format!(
+        "expected {} bytes, found {}",
+        DATA_SIZE,
+        data.len()
+      )));
+    }
+    let chunk_type = ChunkType::try_from(<[u8; 4]>::try_from(&data[..4]).unwrap())?;
+    Ok(Signature {
+      chunk_type,
+      signer: VerifyingKey::from_bytes(data[4..36].try_into().unwrap()),
+      bytes: data[36..].try_into().unwrap(),
+    })
+  }
+}
+
+impl Signature {
+  /// Signs `data` as the contents of a chunk of type `chunk_type`.
+  pub fn sign(key: &SigningKey, chunk_type: &ChunkType, data: &[u8]) -> Self {
+    Signature {
+      chunk_type: *chunk_type,
+      signer: key.verifying_key(),
+      bytes: key.sign(&signed_message(chunk_type, data)),
+    }
+  }
+
+  /// The type of the chunk this signature covers.
+  pub fn chunk_type(&self) -> &ChunkType {
+    &self.chunk_type
+  }
+
+  /// The key that claims to have made the signature.
+  pub fn signer(&self) -> &VerifyingKey {
+    &self.signer
+  }
+
+  /// Checks the signature against the data of the signed chunk.
+  pub fn verify(&self, data: &[u8]) -> Result<()> {
+    if self
+      .signer
+      .verify(&signed_message(&self.chunk_type, data), &self.bytes)
+    {
+      Ok(())
+    } else {
+      Err(Error::BadSignature(self.signer.fingerprint().to_string()))
+    }
+  }
+
+  /// The `siGn` chunk to store after the signed chunk.
+  pub fn to_chunk(&self) -> Chunk {
+    let mut data = Vec::with_capacity(DATA_SIZE);
+    data.extend_from_slice(&self.chunk_type.bytes());
+    data.extend_from_slice(&self.signer.bytes());
+    data.extend_from_slice(&self.bytes);
+    Chunk::new(ChunkType::try_from(SIGNATURE_CHUNK_TYPE).unwrap(), data)
+  }
+}
+
+fn signed_message(chunk_type: &ChunkType, data: &[u8]) -> Vec<u8> {
+  let mut message = Vec::with_capacity(DOMAIN.len() + 4 + data.len());
+  message.extend_from_slice(DOMAIN);
+  message.extend_from_slice(&chunk_type.bytes());
+  message.extend_from_slice(data);
+  message
+}
+
+#[cfg(test)]
+mod tests {
+  use std::str::FromStr;
+
+  use super::*;
+
+  fn key() -> SigningKey {
+    SigningKey::from_bytes([3; 32])
+  }
+
+  #[test]
+  fn test_sign_and_verify() {
+    let chunk_type = ChunkType::from_str("ruSt").unwrap();
+    let signature = Signature::sign(&key(), &chunk_type, b"hidden");
+    assert!(signature.verify(b"hidden").is_ok());
+    assert_eq!(signature.signer(), &key().verifying_key());
+    assert!(matches!(
+      signature.verify(b"hidder"),
+      Err(Error::BadSignature(_))
+    ));
+  }
+
+  #[test]
+  fn test_chunk_round_trip() {
+    let chunk_type = ChunkType::from_str("ruSt").unwrap();
+    let signature = Signature::sign(&key(), &chunk_type, b"hidden");
+    let chunk = signature.to_chunk();
+    assert_eq!(chunk.chunk_type().bytes(), SIGNATURE_CHUNK_TYPE);
+    assert!(chunk.chunk_type().is_valid());
+    assert_eq!(Signature::try_from(chunk.data()).unwrap(), signature);
+  }
+
+  #[test]
+  fn test_signature_is_bound_to_chunk_type() {
+    let signature = Signature::sign(&key(), &ChunkType::from_str("ruSt").unwrap(), b"hidden");
+    let mut data = signature.to_chunk().data().to_vec();
+    data[..4].copy_from_slice(b"ruSx");
+    let moved = Signature::try_from(data.as_slice()).unwrap();
+    assert!(matches!(
+      moved.verify(b"hidden"),
+      Err(Error::BadSignature(_))
+    ));
+  }
+
+  #[test]
+  fn test_malformed_chunk() {
+    assert!(matches!(
+      Signature::try_from(&[0u8; 99][..]),
+      Err(Error::InvalidSignatureChunk(_))
+    ));
+    assert!(Signature::try_from(&[0u8; 100][..]).is_err());
+  }
+}
@@ -0,0 +1,199 @@
+use std::io::{Read, Write};
+
+use crate::{
+  chunk::Chunk,
+  chunk_type::ChunkType,
+  png::Png,
+  reader::{ChunkHeader, ChunkReader},
+  Result,
+};
+
+/// Writes a PNG one chunk at a time to any [`Write`].
+#[derive(Debug)]
+pub struct ChunkWriter<W: Write> {
+  writer: W,
+}
+
+impl<W: Write> ChunkWriter<W> {
+  /// Writes the PNG signature.
+  pub fn new(mut writer: W) -> Result<Self> {
+    writer.write_all(&Png::STANDARD_HEADER)?;
+    Ok(ChunkWriter { writer })
+  }
+
+  pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+    self.writer.write_all(&chunk.as_bytes())?;
+    Ok(())
+  }
+
+  /// Copies the chunk whose header was just read from `reader`, streaming
+  /// its data rather than buffering it.
+  pub fn copy_chunk<R: Read>(
+    &mut self,
+    reader: &mut ChunkReader<R>,
+    header: &ChunkHeader,
+  ) -> Result<()> {
+    self.writer.write_all(&header.length.to_be_bytes())?;
+    self.writer.write_all(&header.chunk_type.bytes())?;
+    let crc = reader.copy_data(&mut self.writer)?;
+    self.writer.write_all(&crc.to_be_bytes())?;
+    Ok(())
+  }
+
+  /// Flushes and returns the underlying writer.
+  pub fn finish(mut self) -> Result<W> {
+    self.writer.flush()?;
+    Ok(self.writer)
+  }
+}
+
+/// What [`StreamEditor`] does with a chunk it is copying.
+#[derive(Debug, Clone)]
+pub enum ChunkAction {
+  Keep,
+  Drop,
+  Replace(Chunk),
+}
+
+/// Copies a PNG from a reader to a writer chunk by chunk, deciding per chunk
+/// type whether to keep, drop or replace it, and inserting new chunks before
+/// `IEND` (or at the end if there is none). Memory use does not depend on
+/// the size of the image.
+pub struct StreamEditor<F> {
+  edit: F,
+  insert: Vec<Chunk>,
+}
+
+impl<F: FnMut(&ChunkType) -> ChunkAction> StreamEditor<F> {
+  pub fn new(edit: F) -> Self {
+    StreamEditor {
+      edit,
+      insert: Vec::new(),
+    }
+  }
+
+  /// Queues `chunk` to be written before `IEND`.
+  pub fn insert(mut self, chunk: Chunk) -> Self {
+    self.insert.push(chunk);
+    self
+  }
+
+  pub fn run<R: Read, W: Write>(mut self, input: R, output: W) -> Result<W> {
+    let mut reader = ChunkReader::new(input)?;
+    let mut writer = ChunkWriter::new(output)?;
+
+    while let Some(header) = reader.next_header()? {
+      if header.chunk_type.bytes() == *b"IEND" {
+        for chunk in self.insert.drain(..) {
+          writer.write_chunk(&chunk)?;
+        }
+      }
+      match (self.edit)(&header.chunk_type) {
+        ChunkAction::Keep => writer.copy_chunk(&mut reader, &header)?,
+        ChunkAction::Drop => reader.skip_data()?,
+        ChunkAction::Replace(chunk) => {
+          reader.skip_data()?;
+          writer.write_chunk(&chunk)?;
+        }
+      }
+    }
+    for chunk in self.insert.drain(..) {
+      writer.write_chunk(&chunk)?;
+    }
+
+    writer.finish()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::str::FromStr;
+
+  fn chunk(chunk_type: &str, data: &str) -> Chunk {
+    Chunk::new(
+      ChunkType::from_str(chunk_type).unwrap(),
+      data.as_bytes().to_vec(),
+    )
+  }
+
+  fn testing_png() -> Png {
+    Png::from_chunks(vec![
+      chunk("IHDR", "header"),
+      chunk("ruSt", "old message"),
+      chunk("IDAT", "pixels"),
+      chunk("IEND", ""),
+    ])
+  }
+
+  fn types(bytes: &[u8]) -> Vec<String> {
+    Png::try_from(bytes)
+      .unwrap()
+      .chunks()
+      .iter()
+      .map(|c| c.chunk_type().to_string())
+      .collect()
+  }
+
+  #[test]
+  fn test_copy_unchanged() {
+    let input = testing_png().as_bytes();
+    let output = StreamEditor::new(|_| ChunkAction::Keep)
+      .run(input.as_slice(), Vec::new())
+      .unwrap();
+    assert_eq!(output, input);
+  }
+
+  #[test]
+  fn test_insert_before_iend() {
+    let input = testing_png().as_bytes();
+    let output = StreamEditor::new(|_| ChunkAction::Keep)
+      .insert(chunk("neWs", "hello"))
+      .run(input.as_slice(), Vec::new())
+      .unwrap();
+    assert_eq!(types(&output), vec!["IHDR", "ruSt", "IDAT", "neWs", "IEND"]);
+  }
+
+  #[test]
+  fn test_insert_without_iend() {
+    let input = Png::from_chunks(vec![chunk("IHDR", "header")]).as_bytes();
+    let output = StreamEditor::new(|_| ChunkAction::Keep)
+      .insert(chunk("neWs", "hello"))
+      .run(input.as_slice(), Vec::new())
+      .unwrap();
+    assert_eq!(types(&output), vec!["IHDR", "neWs"]);
+  }
+
+  #[test]
+  fn test_drop_and_replace() {
+    let input = testing_png().as_bytes();
+    let dropped = StreamEditor::new(|t: &ChunkType| match t.to_string().as_str() {
+      "ruSt" => ChunkAction::Drop,
+      _ => ChunkAction::Keep,
+    })
+    .run(input.as_slice(), Vec::new())
+    .unwrap();
+    assert_eq!(types(&dropped), vec!["IHDR", "IDAT", "IEND"]);
+
+    let replaced = StreamEditor::new(|t: &ChunkType| match t.to_string().as_str() {
+      "ruSt" => ChunkAction::Replace(chunk("ruSt", "new message")),
+      _ => ChunkAction::Keep,
+    })
+    .run(input.as_slice(), Vec::new())
+    .unwrap();
+    let png = Png::try_from(replaced.as_slice()).unwrap();
+    assert_eq!(
+      png.chunk_by_type("ruSt").unwrap().data_as_string().unwrap(),
+      "new message"
+    );
+  }
+
+  #[test]
+  fn test_corrupt_input_is_rejected() {
+    let mut input = testing_png().as_bytes();
+    let last = input.len() - 1;
+    input[last] ^= 1;
+    let result = StreamEditor::new(|_| ChunkAction::Keep).run(input.as_slice(), Vec::new());
+    assert!(result.is_err());
+  }
+}